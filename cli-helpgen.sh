@@ -6,7 +6,18 @@ cargo build
 
 for cmd in session-token \
    assume-role \
+   federation-token \
+   assume-role-with-web-identity \
+   assume-role-with-saml \
+   batch \
+   exec \
+   serve \
+   daemon \
+   "config show" \
+   whoami \
    list \
+   restore \
+   doctor \
    clean; do
-   ./target/debug/mfaws $cmd --help &>./help/$cmd.txt
+   ./target/debug/mfaws $cmd --help &>"./help/${cmd/ /-}.txt"
 done
@@ -1,5 +1,6 @@
 import test from 'ava';
-import { buildBin, setupDir, iniToJSON, runBin } from './utils.js';
+import fs from 'fs-extra';
+import { buildBin, setupDir, iniToJSON, runBin, runBinFailing } from './utils.js';
 
 test.before(async () => {
   const shouldBuild = process.argv.includes('--build');
@@ -73,7 +74,7 @@ test.serial('with otp', async t => {
 
 test.serial('without mfa device', async t => {
   const { credsPath, cleanup } = setupDir();
-  const { stderr } = await runBin(
+  const { stderr, exitCode } = await runBinFailing(
     'session-token',
     '--profile',
     'dev',
@@ -81,12 +82,13 @@ test.serial('without mfa device', async t => {
     credsPath
   );
   t.regex(stderr, /No MFA device found for "dev"/);
+  t.is(exitCode, 1);
   cleanup();
 });
 
 test.serial('with invalid profile', async t => {
   const { credsPath, cleanup } = setupDir();
-  const { stderr } = await runBin(
+  const { stderr, exitCode } = await runBinFailing(
     'session-token',
     '--profile',
     'notexists',
@@ -94,12 +96,13 @@ test.serial('with invalid profile', async t => {
     credsPath
   );
   t.regex(stderr, /Profile "notexists" not found/);
+  t.is(exitCode, 1);
   cleanup();
 });
 
 test.serial('with invalid short-term suffix', async t => {
   const { credsPath, cleanup } = setupDir();
-  const { stderr } = await runBin(
+  const { stderr, exitCode } = await runBinFailing(
     'session-token',
     '--profile',
     'dev-short-term',
@@ -108,25 +111,33 @@ test.serial('with invalid short-term suffix', async t => {
   );
 
   t.regex(stderr, /Profile name cannot end with the short-term suffix/);
+  t.is(exitCode, 1);
   cleanup();
 });
 
 test.serial('with invalid credentials', async t => {
   const { cleanup } = setupDir();
-  const { stderr } = await runBin(
+  const { stderr, exitCode } = await runBinFailing(
     'session-token',
     '--credentials-path',
     'doesnotexist'
   );
 
   t.regex(stderr, /Failed to load credentials file/);
+  t.is(exitCode, 1);
   cleanup();
 });
 
 test.serial('list profiles', async t => {
   const { cleanup, credsPath } = setupDir();
-  const { stdout } = await runBin('list', '--credentials-path', credsPath);
-  t.regex(stdout, /Found 2 AWS credential profiles:\ndefault\ndev/);
+  {
+    const { stdout } = await runBin('list', '--credentials-path', credsPath);
+    t.regex(stdout, /Found 2 AWS credential profiles:\nNAME .*\ndefault +long-term .*\ndev +long-term /);
+  }
+  {
+    const { stdout } = await runBin('list', '--output', 'plain', '--credentials-path', credsPath);
+    t.is(stdout, 'default\ndev\n');
+  }
   cleanup();
 });
 
@@ -175,3 +186,35 @@ test.serial('cleanup', async t => {
   }
   cleanup();
 });
+
+test.serial('doctor', async t => {
+  const { cleanup, credsPath } = setupDir();
+  fs.chmodSync(credsPath, 0o644);
+  {
+    const { stderr, exitCode } = await runBinFailing('doctor', '--credentials-path', credsPath);
+    t.regex(stderr, /is accessible by other users \(mode 644\)/);
+    t.is(exitCode, 1);
+  }
+  {
+    const { stderr, exitCode } = await runBinFailing(
+      'list',
+      '--permissions',
+      'refuse',
+      '--credentials-path',
+      credsPath
+    );
+    t.regex(stderr, /Run `mfaws doctor --fix` to fix the permissions/);
+    t.is(exitCode, 1);
+  }
+  {
+    const { exitCode } = await runBin('doctor', '--fix', '--credentials-path', credsPath);
+    t.is(exitCode, 0);
+    t.is(fs.statSync(credsPath).mode & 0o777, 0o600);
+  }
+  {
+    const { stdout, exitCode } = await runBin('doctor', '--credentials-path', credsPath);
+    t.regex(stdout, /No problems found/);
+    t.is(exitCode, 0);
+  }
+  cleanup();
+});
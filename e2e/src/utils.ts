@@ -27,6 +27,11 @@ export function runBin(...args: string[]) {
   return execa(EXECUTABLE, args, { all: true });
 }
 
+// Like runBin, but resolves instead of rejecting when the exit code is not 0
+export function runBinFailing(...args: string[]) {
+  return execa(EXECUTABLE, args, { all: true, reject: false });
+}
+
 export function iniToJSON(iniFilePath: string) {
   return ini.parse(fs.readFileSync(iniFilePath, 'utf-8'));
 }
@@ -0,0 +1,354 @@
+use std::{fmt::Debug, path::Path};
+
+use ini::{self, Ini, Properties};
+use thiserror::Error;
+
+use crate::config::Config;
+
+/// Handler for the shared AWS config file (`~/.aws/config`). Unlike the
+/// credentials file, profile sections other than `default` must be prefixed
+/// with `profile ` (e.g. `[profile dev]`).
+pub struct AwsConfigHandler {
+    pub ini: Ini,
+}
+
+/// Settings of a single profile in the AWS config file that are relevant to
+/// mfaws.
+#[derive(Debug, Default, PartialEq)]
+pub struct ConfigProfile {
+    pub role_arn: Option<String>,
+    pub source_profile: Option<String>,
+    pub mfa_serial: Option<String>,
+    pub mfa_process: Option<String>,
+    pub duration_seconds: Option<i32>,
+    pub role_session_name: Option<String>,
+    pub web_identity_token_file: Option<String>,
+    pub external_id: Option<String>,
+}
+
+/// The roles to assume, in order, to get from a source profile with
+/// long-term credentials to the requested profile.
+#[derive(Debug, Default, PartialEq)]
+pub struct RoleChain {
+    /// Role profiles, starting with the requested profile and ending with the
+    /// one that directly uses the source profile
+    pub roles: Vec<ConfigProfile>,
+    pub source_profile: String,
+}
+
+impl ConfigProfile {
+    pub const ROLE_ARN: &'static str = "role_arn";
+    pub const SOURCE_PROFILE: &'static str = "source_profile";
+    pub const MFA_SERIAL: &'static str = "mfa_serial";
+    pub const MFA_PROCESS: &'static str = "mfa_process";
+    pub const DURATION_SECONDS: &'static str = "duration_seconds";
+    pub const ROLE_SESSION_NAME: &'static str = "role_session_name";
+    pub const WEB_IDENTITY_TOKEN_FILE: &'static str = "web_identity_token_file";
+    pub const EXTERNAL_ID: &'static str = "external_id";
+}
+
+impl Debug for AwsConfigHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[Placeholder]")
+    }
+}
+
+impl TryFrom<&Config> for AwsConfigHandler {
+    type Error = anyhow::Error;
+    fn try_from(config: &Config) -> Result<AwsConfigHandler, Self::Error> {
+        AwsConfigHandler::from_file(config.config_path.as_path())
+    }
+}
+
+impl AwsConfigHandler {
+    const PROFILE_PREFIX: &'static str = "profile";
+    const DEFAULT_PROFILE: &'static str = "default";
+
+    pub(crate) fn _new(buf: &str) -> Result<Self, ini::ParseError> {
+        Ok(Self {
+            ini: Ini::load_from_str(buf)?,
+        })
+    }
+
+    /// The config file is optional. If it does not exist, an empty config is
+    /// used.
+    pub fn from_file<P>(path: P) -> anyhow::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let ini = match path.as_ref().exists() {
+            true => Ini::load_from_file(path.as_ref())
+                .map_err(|e| anyhow::anyhow!("Failed to load config file: {}", e))?,
+            false => Ini::new(),
+        };
+        Ok(Self { ini })
+    }
+
+    /// Resolves the name of a config file section to a profile name,
+    /// following the same rules as the AWS CLI: `[profile x]` defines
+    /// profile `x`, `[default]` defines the default profile and any other
+    /// section is not a profile. Like the section names in the INI reader,
+    /// they are case-insensitive.
+    fn profile_name_of(section: &str) -> Option<&str> {
+        let rest = section
+            .get(..Self::PROFILE_PREFIX.len())
+            .filter(|prefix| prefix.eq_ignore_ascii_case(Self::PROFILE_PREFIX))
+            .map(|prefix| &section[prefix.len()..]);
+        match rest {
+            Some(rest) if rest.starts_with(char::is_whitespace) => Some(rest.trim()),
+            _ if section.eq_ignore_ascii_case(Self::DEFAULT_PROFILE) => Some(Self::DEFAULT_PROFILE),
+            _ => None,
+        }
+    }
+
+    fn get_section(&self, profile_name: &str) -> Option<&Properties> {
+        let mut sections = self
+            .ini
+            .iter()
+            .filter_map(|(name, props)| Some((name?, props)))
+            .filter(|(name, _)| {
+                Self::profile_name_of(name)
+                    .is_some_and(|name| name.eq_ignore_ascii_case(profile_name))
+            });
+
+        // "[profile default]" takes precedence over "[default]"
+        let first = sections.next()?;
+        match sections.next() {
+            Some(second) if first.0.eq_ignore_ascii_case(Self::DEFAULT_PROFILE) => Some(second.1),
+            _ => Some(first.1),
+        }
+    }
+
+    pub fn get_profile(&self, profile_name: &str) -> Result<Option<ConfigProfile>, AwsConfigError> {
+        let Some(section) = self.get_section(profile_name) else {
+            return Ok(None);
+        };
+        let get = |key: &str| section.get(key).map(|v| v.to_owned());
+        let duration_seconds = match section.get(ConfigProfile::DURATION_SECONDS) {
+            Some(value) => Some(value.parse::<i32>().map_err(|_| {
+                AwsConfigError::InvalidValue(
+                    ConfigProfile::DURATION_SECONDS.to_owned(),
+                    profile_name.to_owned(),
+                )
+            })?),
+            None => None,
+        };
+
+        Ok(Some(ConfigProfile {
+            role_arn: get(ConfigProfile::ROLE_ARN),
+            source_profile: get(ConfigProfile::SOURCE_PROFILE),
+            mfa_serial: get(ConfigProfile::MFA_SERIAL),
+            mfa_process: get(ConfigProfile::MFA_PROCESS),
+            duration_seconds,
+            role_session_name: get(ConfigProfile::ROLE_SESSION_NAME),
+            web_identity_token_file: get(ConfigProfile::WEB_IDENTITY_TOKEN_FILE),
+            external_id: get(ConfigProfile::EXTERNAL_ID),
+        }))
+    }
+
+    /// Follows the `source_profile` of role profiles until a profile without
+    /// a `role_arn` (or without a `source_profile`) is found. A role profile
+    /// that is its own `source_profile` uses its own static credentials,
+    /// like in the AWS CLI.
+    pub fn get_role_chain(&self, profile_name: &str) -> Result<RoleChain, AwsConfigError> {
+        let mut chain = RoleChain {
+            source_profile: profile_name.to_owned(),
+            ..Default::default()
+        };
+        let mut visited = vec![profile_name.to_owned()];
+
+        while let Some(profile) = self.get_profile(&chain.source_profile)? {
+            if profile.role_arn.is_none() {
+                break;
+            }
+            let source_profile = profile.source_profile.clone();
+            chain.roles.push(profile);
+            match source_profile {
+                Some(source_profile)
+                    if source_profile.eq_ignore_ascii_case(&chain.source_profile) =>
+                {
+                    break
+                }
+                Some(source_profile)
+                    if visited
+                        .iter()
+                        .any(|name| name.eq_ignore_ascii_case(&source_profile)) =>
+                {
+                    return Err(AwsConfigError::SourceProfileCycle(source_profile))
+                }
+                Some(source_profile) => {
+                    visited.push(source_profile.clone());
+                    chain.source_profile = source_profile;
+                }
+                None => break,
+            }
+        }
+        Ok(chain)
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum AwsConfigError {
+    #[error("Invalid value for \"{0}\" in config profile \"{1}\"")]
+    InvalidValue(String, String),
+    #[error("Profile \"{0}\" is part of a source_profile cycle")]
+    SourceProfileCycle(String),
+}
+
+#[cfg(test)]
+mod test_aws_config {
+    use super::*;
+
+    #[test]
+    fn requires_profile_prefix() {
+        let ini = r#"[dev]
+        role_arn = bare
+        [profile dev]
+        role_arn = prefixed"#;
+        let handler = AwsConfigHandler::_new(ini).unwrap();
+        let profile = handler.get_profile("dev").unwrap().unwrap();
+        assert_eq!(profile.role_arn.as_deref(), Some("prefixed"));
+        assert!(handler.get_profile("profile").unwrap().is_none());
+    }
+
+    #[test]
+    fn ignores_bare_sections() {
+        let ini = r#"[dev]
+        role_arn = bare
+        [sso-session dev]
+        role_arn = sso"#;
+        let handler = AwsConfigHandler::_new(ini).unwrap();
+        assert!(handler.get_profile("dev").unwrap().is_none());
+    }
+
+    #[test]
+    fn resolves_default_profile() {
+        let handler = AwsConfigHandler::_new("[default]\nmfa_serial = bare").unwrap();
+        let profile = handler.get_profile("default").unwrap().unwrap();
+        assert_eq!(profile.mfa_serial.as_deref(), Some("bare"));
+
+        let ini = r#"[default]
+        mfa_serial = bare
+        [profile default]
+        mfa_serial = prefixed"#;
+        let handler = AwsConfigHandler::_new(ini).unwrap();
+        let profile = handler.get_profile("default").unwrap().unwrap();
+        assert_eq!(profile.mfa_serial.as_deref(), Some("prefixed"));
+    }
+
+    #[test]
+    fn reads_all_keys() {
+        let ini = r#"[profile admin]
+        role_arn = arn:aws:iam::123456789012:role/admin
+        source_profile = dev
+        mfa_serial = arn:aws:iam::123456789012:mfa/user
+        mfa_process = ykman oath accounts code -s aws
+        duration_seconds = 900
+        role_session_name = session
+        web_identity_token_file = /var/run/token
+        external_id = third-party"#;
+        let handler = AwsConfigHandler::_new(ini).unwrap();
+        assert_eq!(
+            handler.get_profile("admin").unwrap().unwrap(),
+            ConfigProfile {
+                role_arn: Some("arn:aws:iam::123456789012:role/admin".to_owned()),
+                source_profile: Some("dev".to_owned()),
+                mfa_serial: Some("arn:aws:iam::123456789012:mfa/user".to_owned()),
+                mfa_process: Some("ykman oath accounts code -s aws".to_owned()),
+                duration_seconds: Some(900),
+                role_session_name: Some("session".to_owned()),
+                web_identity_token_file: Some("/var/run/token".to_owned()),
+                external_id: Some("third-party".to_owned()),
+            }
+        );
+    }
+
+    #[test]
+    fn resolves_role_chain() {
+        let ini = r#"[profile break-glass]
+        role_arn = arn:aws:iam::333333333333:role/break-glass
+        source_profile = workload
+        [profile workload]
+        role_arn = arn:aws:iam::222222222222:role/workload
+        source_profile = identity
+        [profile identity]
+        role_arn = arn:aws:iam::111111111111:role/identity
+        source_profile = dev
+        [profile dev]
+        region = eu-central-1"#;
+        let handler = AwsConfigHandler::_new(ini).unwrap();
+        let chain = handler.get_role_chain("break-glass").unwrap();
+        assert_eq!(chain.source_profile, "dev");
+        assert_eq!(
+            chain
+                .roles
+                .iter()
+                .map(|p| p.role_arn.as_deref().unwrap())
+                .collect::<Vec<_>>(),
+            vec![
+                "arn:aws:iam::333333333333:role/break-glass",
+                "arn:aws:iam::222222222222:role/workload",
+                "arn:aws:iam::111111111111:role/identity",
+            ]
+        );
+
+        let chain = handler.get_role_chain("dev").unwrap();
+        assert_eq!(chain.source_profile, "dev");
+        assert!(chain.roles.is_empty());
+    }
+
+    #[test]
+    fn resolves_self_sourced_profile() {
+        let ini = r#"[profile admin]
+        role_arn = arn:aws:iam::222222222222:role/admin
+        source_profile = identity
+        [profile identity]
+        role_arn = arn:aws:iam::111111111111:role/identity
+        source_profile = identity"#;
+        let handler = AwsConfigHandler::_new(ini).unwrap();
+        let chain = handler.get_role_chain("admin").unwrap();
+        assert_eq!(chain.source_profile, "identity");
+        assert_eq!(chain.roles.len(), 2);
+
+        let chain = handler.get_role_chain("identity").unwrap();
+        assert_eq!(chain.source_profile, "identity");
+        assert_eq!(chain.roles.len(), 1);
+    }
+
+    #[test]
+    fn matches_profile_names_case_insensitively() {
+        let ini = r#"[Profile Dev]
+        mfa_serial = dev
+        [DEFAULT]
+        mfa_serial = default"#;
+        let handler = AwsConfigHandler::_new(ini).unwrap();
+        let profile = handler.get_profile("dev").unwrap().unwrap();
+        assert_eq!(profile.mfa_serial.as_deref(), Some("dev"));
+        let profile = handler.get_profile("default").unwrap().unwrap();
+        assert_eq!(profile.mfa_serial.as_deref(), Some("default"));
+    }
+
+    #[test]
+    fn err_role_chain_cycle() {
+        let ini = r#"[profile a]
+        role_arn = arn:aws:iam::111111111111:role/a
+        source_profile = b
+        [profile b]
+        role_arn = arn:aws:iam::111111111111:role/b
+        source_profile = a"#;
+        let handler = AwsConfigHandler::_new(ini).unwrap();
+        assert!(matches!(
+            handler.get_role_chain("a").unwrap_err(),
+            AwsConfigError::SourceProfileCycle(_)
+        ));
+    }
+
+    #[test]
+    fn err_invalid_duration() {
+        let handler = AwsConfigHandler::_new("[profile dev]\nduration_seconds = 1h").unwrap();
+        assert!(matches!(
+            handler.get_profile("dev").unwrap_err(),
+            AwsConfigError::InvalidValue(_, _)
+        ));
+    }
+}
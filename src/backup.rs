@@ -0,0 +1,145 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use aws_smithy_types::{date_time::Format, DateTime};
+
+use crate::permissions::{create_private_dir, private_file_options};
+
+/// The directory next to the credentials file that holds the backups
+const BACKUP_DIR: &str = "mfaws-backups";
+
+/// Timestamped copies of the credentials file, taken before every write.
+/// Only the newest ones are kept. Backups contain long-term keys, so they
+/// are only readable by the owner.
+#[derive(Debug)]
+pub struct Backups {
+    dir: PathBuf,
+    /// Backups are named `<file name>.<timestamp>`
+    prefix: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backup {
+    pub path: PathBuf,
+    /// When the backup was taken, as in its file name
+    pub timestamp: String,
+}
+
+impl Backups {
+    pub fn for_file(path: &Path) -> Self {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        Self {
+            dir: path.with_file_name(BACKUP_DIR),
+            prefix: format!("{}.", file_name),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The backups, newest first
+    pub fn list(&self) -> Vec<Backup> {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return vec![];
+        };
+        let mut backups = entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                let timestamp = name.strip_prefix(&self.prefix)?.to_owned();
+                Some(Backup {
+                    path: entry.path(),
+                    timestamp,
+                })
+            })
+            .collect::<Vec<_>>();
+        // Timestamps sort chronologically
+        backups.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        backups
+    }
+
+    /// Backs up the contents of the file, unless the newest backup has the
+    /// same contents, and deletes all but the newest `keep` backups
+    pub fn create(&self, contents: &str, keep: usize) -> anyhow::Result<()> {
+        let newest = self.list().into_iter().next();
+        if newest.is_some_and(|b| std::fs::read_to_string(b.path).is_ok_and(|c| c == contents)) {
+            return Ok(());
+        }
+        create_private_dir(&self.dir)?;
+        let path = self.dir.join(format!("{}{}", self.prefix, timestamp()));
+        let mut file = private_file_options()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+
+        for backup in self.list().into_iter().skip(keep) {
+            std::fs::remove_file(backup.path)?;
+        }
+        Ok(())
+    }
+}
+
+/// The current time as `2024-05-01T10-15-00.123Z`, which is a valid file name
+/// on every platform and sorts chronologically
+fn timestamp() -> String {
+    let now = DateTime::from(SystemTime::now());
+    let secs = DateTime::from_secs(now.secs())
+        .fmt(Format::DateTime)
+        .unwrap_or_default();
+    format!(
+        "{}.{:03}Z",
+        secs.trim_end_matches('Z').replace(':', "-"),
+        now.subsec_nanos() / 1_000_000
+    )
+}
+
+#[cfg(test)]
+mod test_backup {
+    use super::*;
+
+    #[test]
+    fn formats_timestamp() {
+        let timestamp = timestamp();
+        assert_eq!(timestamp.len(), "2024-05-01T10-15-00.123Z".len());
+        assert!(!timestamp.contains(':'));
+    }
+
+    #[test]
+    fn rotates_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let backups = Backups::for_file(&dir.path().join("credentials"));
+        assert!(backups.list().is_empty());
+
+        for i in 0..5 {
+            backups.create(&format!("[profile-{i}]"), 3).unwrap();
+            // Unchanged contents are not backed up again
+            backups.create(&format!("[profile-{i}]"), 3).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        let list = backups.list();
+        assert_eq!(list.len(), 3);
+        let newest = std::fs::read_to_string(&list[0].path).unwrap();
+        assert_eq!(newest, "[profile-4]");
+        assert!(list[0].path.starts_with(dir.path().join(BACKUP_DIR)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn backups_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let backups = Backups::for_file(&dir.path().join("credentials"));
+        backups.create("[dev]", 1).unwrap();
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&dir.path().join(BACKUP_DIR)), 0o700);
+        assert_eq!(mode(&backups.list()[0].path), 0o600);
+    }
+}
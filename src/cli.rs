@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches};
 
 use crate::{cmds::SubCommand, config::Config};
 
@@ -12,6 +12,24 @@ pub struct Cli {
 }
 
 pub fn parse() -> (SubCommand, Config) {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if let Some(config) = cli.command.sts_config_mut() {
+        config.defaulted = defaulted_args(&matches);
+    }
     (cli.command, cli.config)
 }
+
+/// The args of the innermost subcommand that use their default value, i.e.
+/// that were neither given as flags nor as environment variables
+fn defaulted_args(matches: &ArgMatches) -> Vec<String> {
+    let mut matches = matches;
+    while let Some((_, sub_matches)) = matches.subcommand() {
+        matches = sub_matches;
+    }
+    matches
+        .ids()
+        .filter(|id| matches.value_source(id.as_str()) == Some(ValueSource::DefaultValue))
+        .map(|id| id.to_string())
+        .collect()
+}
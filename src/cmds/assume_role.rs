@@ -1,13 +1,17 @@
 use crate::{
+    aws_config::AwsConfigHandler,
     cmds::Command,
     config::Config,
     creds::CredentialsHandler,
+    mfaws_config::MfawsConfig,
     sts::{assume_role::AssumeRole, get_st_profile},
 };
 
 impl Command for AssumeRole {
     async fn exec(mut self, config: &Config) -> anyhow::Result<()> {
-        self.config.init()?;
+        let aws_config = AwsConfigHandler::try_from(config)?;
+        self.apply_alias(&MfawsConfig::try_from(config)?)?;
+        self.init(&aws_config)?;
         let creds_handler = CredentialsHandler::try_from(config)?;
         get_st_profile(self, creds_handler).await
     }
@@ -0,0 +1,384 @@
+use std::{path::PathBuf, sync::Arc};
+
+use tokio::task::JoinSet;
+
+use crate::{
+    aws_config::AwsConfigHandler,
+    cmds::Command,
+    config::Config,
+    creds::CredentialsHandler,
+    mfaws_config::MfawsConfig,
+    profile::ShortTermProfile,
+    sts::{
+        assume_role::AssumeRole, config::CommonStsConfig, request_st_profile,
+        session_token::SessionToken, ShortTermCredentials, MIN_PARENT_VALIDITY,
+    },
+};
+
+#[derive(clap::Args, Debug, Default)]
+#[command(
+    mut_arg("output", |arg| arg.hide(true)),
+    mut_arg("shell", |arg| arg.hide(true))
+)]
+pub struct Batch {
+    #[arg(
+        value_name = "ROLE",
+        help = "Role ARNs, mfaws aliases or names of role profiles in the AWS config file"
+    )]
+    pub roles: Vec<String>,
+    #[arg(
+        long = "roles-file",
+        help = "A file with one role ARN, alias or profile name per line. Empty lines and lines starting with # are ignored"
+    )]
+    pub roles_file: Option<PathBuf>,
+    #[arg(
+        long = "role-session-name",
+        env = "AWS_ROLE_SESSION_NAME",
+        help = "Custom friendly session name when assuming the roles [default: mfa-user]"
+    )]
+    pub role_name: Option<String>,
+    #[clap(flatten)]
+    pub config: CommonStsConfig,
+}
+
+/// What happened to a single role of the batch
+enum Outcome {
+    Created {
+        profile_name: String,
+        expiration: String,
+    },
+    Valid {
+        profile_name: String,
+        remaining_time: String,
+    },
+    Failed(anyhow::Error),
+}
+
+impl Batch {
+    fn role_specs(&self) -> anyhow::Result<Vec<String>> {
+        let mut specs = self.roles.clone();
+        if let Some(ref path) = self.roles_file {
+            let content = std::fs::read_to_string(path).map_err(|e| {
+                anyhow::anyhow!("Failed to read roles file \"{}\": {}", path.display(), e)
+            })?;
+            specs.extend(parse_roles(&content));
+        }
+        let mut unique = Vec::with_capacity(specs.len());
+        for spec in specs {
+            if !unique.contains(&spec) {
+                unique.push(spec);
+            }
+        }
+        Ok(unique)
+    }
+
+    /// A role is either an ARN that is assumed with the given profile, an
+    /// alias from the mfaws config file or the name of a role profile in the
+    /// AWS config file. All roles must use the same long-term profile,
+    /// otherwise the session token does not apply.
+    fn resolve_role(
+        &self,
+        spec: &str,
+        flags: &CommonStsConfig,
+        aws_config: &AwsConfigHandler,
+        mfaws_config: &MfawsConfig,
+    ) -> anyhow::Result<AssumeRole> {
+        let mut cmd = AssumeRole {
+            role_name: self.role_name.clone(),
+            config: flags.clone(),
+            ..Default::default()
+        };
+        if spec.starts_with("arn:") {
+            cmd.role_arn = Some(spec.to_owned());
+        } else if mfaws_config.aliases.contains_key(spec) {
+            cmd.alias = Some(spec.to_owned());
+            cmd.apply_alias(mfaws_config)?;
+        } else {
+            cmd.config.profile_name = spec.to_owned();
+        }
+        cmd.init(aws_config)?;
+        if cmd.config.profile_name != self.config.profile_name {
+            anyhow::bail!(
+                "Profile \"{}\" uses the long-term profile \"{}\" instead of \"{}\"",
+                spec,
+                cmd.config.profile_name,
+                self.config.profile_name
+            );
+        }
+        Ok(cmd)
+    }
+
+    /// A session token from the credentials file that is valid long enough
+    /// to sign the roles
+    fn cached_parent(
+        handler: &CredentialsHandler,
+        parent_name: &str,
+    ) -> Option<ShortTermProfile<'static>> {
+        handler
+            .get_short_term_profile(parent_name)
+            .filter(|parent| !parent.expires_within(MIN_PARENT_VALIDITY))
+    }
+
+    /// The session token that signs all roles. If there is no cached one, a
+    /// new one is requested with a single MFA code.
+    async fn parent_session(
+        session: &SessionToken,
+        handler: &mut CredentialsHandler,
+    ) -> anyhow::Result<ShortTermProfile<'static>> {
+        let parent_name = session.short_profile_name();
+        if let Some(parent) = Self::cached_parent(handler, &parent_name) {
+            info!("Using session token \"{}\"", parent_name);
+            return Ok(parent);
+        }
+        let (parent, source) = request_st_profile(session, handler).await?;
+        let parent = parent.into_owned();
+        handler.set_short_term_profile(&parent, &parent_name);
+        handler.set_profile_source(&source, &parent_name);
+        Ok(parent)
+    }
+
+    fn print_summary(specs: &[String], outcomes: &[Outcome]) {
+        info!("Summary:");
+        for (spec, outcome) in specs.iter().zip(outcomes) {
+            match outcome {
+                Outcome::Created {
+                    profile_name,
+                    expiration,
+                } => println!(
+                    "ok      {} -> \"{}\" (valid until {})",
+                    spec, profile_name, expiration
+                ),
+                Outcome::Valid {
+                    profile_name,
+                    remaining_time,
+                } => println!(
+                    "valid   {} -> \"{}\" (valid for the next {})",
+                    spec, profile_name, remaining_time
+                ),
+                Outcome::Failed(err) => println!("failed  {}: {}", spec, err),
+            }
+        }
+    }
+}
+
+fn parse_roles(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_owned())
+        .collect()
+}
+
+impl Command for Batch {
+    async fn exec(mut self, config: &Config) -> anyhow::Result<()> {
+        let specs = self.role_specs()?;
+        if specs.is_empty() {
+            anyhow::bail!("No roles given, pass role ARNs or profile names or use --roles-file");
+        }
+        let aws_config = AwsConfigHandler::try_from(config)?;
+        let mfaws_config = MfawsConfig::try_from(config)?;
+        // Every role resolves its own settings from the flags
+        let flags = self.config.clone();
+        self.config.init(&aws_config)?;
+        let mut creds_handler = CredentialsHandler::try_from_locked(config)?;
+
+        let mut outcomes = Vec::with_capacity(specs.len());
+        let mut pending = Vec::new();
+        for (index, spec) in specs.iter().enumerate() {
+            let outcome = match self.resolve_role(spec, &flags, &aws_config, &mfaws_config) {
+                Ok(cmd) => match creds_handler.is_profile_still_valid(&cmd.short_profile_name()) {
+                    Some(remaining_time) if !cmd.config.force_new_credentials => {
+                        Some(Outcome::Valid {
+                            profile_name: cmd.short_profile_name(),
+                            remaining_time,
+                        })
+                    }
+                    _ => {
+                        pending.push((index, cmd));
+                        None
+                    }
+                },
+                Err(err) => Some(Outcome::Failed(err)),
+            };
+            outcomes.push(outcome);
+        }
+
+        let session = SessionToken {
+            config: self.config.clone(),
+        };
+        let parent_name = session.short_profile_name();
+        let parent = match pending.is_empty() {
+            true => None,
+            // The roles that are left cannot be assumed without it, but the
+            // summary still shows what happened to every role
+            false => match Self::parent_session(&session, &mut creds_handler).await {
+                Ok(parent) => Some(Arc::new(parent)),
+                Err(err) => {
+                    for (index, _) in pending.drain(..) {
+                        outcomes[index] = Some(Outcome::Failed(anyhow::anyhow!(
+                            "Failed to get the session token: {}",
+                            err
+                        )));
+                    }
+                    None
+                }
+            },
+        };
+
+        if let Some(parent) = parent {
+            let mut tasks = JoinSet::new();
+            for (index, cmd) in pending {
+                cmd.log_action();
+                let parent = Arc::clone(&parent);
+                tasks.spawn(async move {
+                    let result = cmd
+                        .get_credentials_with_session(&cmd.config, &parent)
+                        .await
+                        .map(ShortTermProfile::into_owned);
+                    (index, cmd, result)
+                });
+            }
+
+            let mut created = 0;
+            while let Some(task) = tasks.join_next().await {
+                let (index, cmd, result) = task?;
+                outcomes[index] = Some(match result {
+                    Ok(st_profile) => {
+                        let profile_name = cmd.short_profile_name();
+                        let mut source = cmd.profile_source();
+                        source.parent = Some(parent_name.clone());
+                        creds_handler.set_short_term_profile(&st_profile, &profile_name);
+                        creds_handler.set_profile_source(&source, &profile_name);
+                        created += 1;
+                        Outcome::Created {
+                            profile_name,
+                            expiration: st_profile.format_expiration(),
+                        }
+                    }
+                    Err(err) => Outcome::Failed(err),
+                });
+            }
+            // All profiles are written at once
+            creds_handler.to_file()?;
+            info!("Successfully added {} short-term profile(s)", created);
+        }
+
+        let outcomes = outcomes.into_iter().flatten().collect::<Vec<_>>();
+        Self::print_summary(&specs, &outcomes);
+
+        let failed = outcomes
+            .iter()
+            .filter(|outcome| matches!(outcome, Outcome::Failed(_)))
+            .count();
+        if failed > 0 {
+            anyhow::bail!("{} of {} roles failed", failed, specs.len());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_batch {
+    use super::*;
+
+    const AWS_CONFIG: &str = r#"[profile admin]
+    role_arn = arn:aws:iam::222222222222:role/admin
+    source_profile = dev
+    [profile other]
+    role_arn = arn:aws:iam::333333333333:role/other
+    source_profile = prod"#;
+
+    fn batch() -> Batch {
+        Batch {
+            config: CommonStsConfig {
+                profile_name: "dev".to_owned(),
+                short_term_suffix: "short-term".to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reuses_parent_valid_long_enough() {
+        let in_a_moment = aws_smithy_types::DateTime::from(
+            std::time::SystemTime::now() + std::time::Duration::from_secs(10),
+        )
+        .fmt(aws_smithy_types::date_time::Format::DateTime)
+        .unwrap();
+        let handler = CredentialsHandler::_new(&format!(
+            r#"[dev-short-term]
+            aws_access_key_id = 1
+            aws_secret_access_key = 1
+            aws_session_token = 1
+            expiration = 2099-01-01T00:00:00Z
+            [prod-short-term]
+            aws_access_key_id = 1
+            aws_secret_access_key = 1
+            aws_session_token = 1
+            expiration = {in_a_moment}"#
+        ))
+        .unwrap();
+        assert!(Batch::cached_parent(&handler, "dev-short-term").is_some());
+        assert!(Batch::cached_parent(&handler, "prod-short-term").is_none());
+        assert!(Batch::cached_parent(&handler, "missing-short-term").is_none());
+    }
+
+    #[test]
+    fn parses_roles_file() {
+        let content = r#"
+        # Production
+        arn:aws:iam::111111111111:role/admin
+
+        admin
+        "#;
+        assert_eq!(
+            parse_roles(content),
+            vec!["arn:aws:iam::111111111111:role/admin", "admin"]
+        );
+    }
+
+    #[test]
+    fn resolves_role_arns_and_profiles() {
+        let aws_config = AwsConfigHandler::_new(AWS_CONFIG).unwrap();
+        let mfaws_config = MfawsConfig::_new(
+            r#"[aliases.readonly]
+            role_arn = "arn:aws:iam::444444444444:role/readonly""#,
+        )
+        .unwrap();
+        let batch = batch();
+
+        let cmd = batch
+            .resolve_role(
+                "arn:aws:iam::111111111111:role/admin",
+                &batch.config,
+                &aws_config,
+                &mfaws_config,
+            )
+            .unwrap();
+        assert_eq!(cmd.role_arn(), "arn:aws:iam::111111111111:role/admin");
+        assert_eq!(cmd.config.profile_name, "dev");
+
+        let cmd = batch
+            .resolve_role("admin", &batch.config, &aws_config, &mfaws_config)
+            .unwrap();
+        assert_eq!(cmd.role_arn(), "arn:aws:iam::222222222222:role/admin");
+        assert_eq!(cmd.config.profile_name, "dev");
+
+        let cmd = batch
+            .resolve_role("readonly", &batch.config, &aws_config, &mfaws_config)
+            .unwrap();
+        assert_eq!(cmd.role_arn(), "arn:aws:iam::444444444444:role/readonly");
+        assert_eq!(cmd.config.profile_name, "dev");
+    }
+
+    #[test]
+    fn err_role_with_other_source_profile() {
+        let aws_config = AwsConfigHandler::_new(AWS_CONFIG).unwrap();
+        let batch = batch();
+        assert!(batch
+            .resolve_role("other", &batch.config, &aws_config, &MfawsConfig::default())
+            .is_err());
+    }
+}
@@ -1,5 +1,16 @@
-use crate::utils::confirm_prompt;
-use crate::{cmds::Command, config::Config, creds::CredentialsHandler};
+use std::time::{Duration, SystemTime};
+
+use crate::{
+    cmds::Command,
+    config::Config,
+    creds::CredentialsHandler,
+    profile::{Profile, ProfileKind, ProfileSource, ShortTermProfile},
+    sts::{
+        assume_role::AssumeRole, federation_token::FederationToken, saml::Saml,
+        session_token::SessionToken, web_identity::WebIdentity, ShortTermCredentials,
+    },
+    utils::{confirm_prompt, parse_duration},
+};
 
 #[derive(clap::Args, Debug, Default)]
 pub struct Clean {
@@ -9,30 +20,292 @@ pub struct Clean {
         help = "To identify the short-term credential profiles"
     )]
     pub short_term_suffix: String,
+    #[arg(long, help = "Only delete expired short-term profiles")]
+    pub expired: bool,
+    #[arg(
+        long = "profile",
+        value_name = "PROFILE",
+        help = "Only delete short-term profiles derived from this long-term profile"
+    )]
+    pub source_profile: Option<String>,
+    #[arg(
+        long = "role-arn",
+        help = "Only delete short-term profiles of this role"
+    )]
+    pub role_arn: Option<String>,
+    #[arg(
+        long = "older-than",
+        value_name = "AGE",
+        value_parser = parse_duration,
+        help = "Only delete short-term profiles issued longer ago than this, e.g. 12h or 7d"
+    )]
+    pub older_than: Option<Duration>,
+    #[arg(
+        long = "dry-run",
+        help = "Only print the profiles that would be deleted"
+    )]
+    pub dry_run: bool,
+    #[arg(short, long, help = "Delete without asking for confirmation")]
+    pub yes: bool,
+}
+
+impl Clean {
+    /// Filters are combined, a profile is only deleted if it matches all of
+    /// them. Profiles that lack the information a filter needs, e.g. ones
+    /// written by older versions of mfaws, do not match it. `--profile`
+    /// matches the profile with the long-term credentials, so roles of role
+    /// profiles match the profile at the end of their role chain.
+    fn matches(&self, handler: &CredentialsHandler, name: &str) -> bool {
+        if self.expired && handler.is_profile_still_valid(name).is_some() {
+            return false;
+        }
+        let source = handler.get_profile_source(name);
+        if let Some(ref profile) = self.source_profile {
+            let long_term_profile = source
+                .as_ref()
+                .map(|s| s.long_term_profile.as_ref().unwrap_or(&s.profile));
+            if long_term_profile != Some(profile) {
+                return false;
+            }
+        }
+        if let Some(ref role_arn) = self.role_arn {
+            let assumed_role_arn = handler
+                .get_profile(name)
+                .and_then(|section| section.get(ShortTermProfile::ASSUMED_ROLE_ARN));
+            if !assumed_role_arn.is_some_and(|arn| is_role_session(role_arn, arn)) {
+                return false;
+            }
+        }
+        if let Some(age) = self.older_than {
+            match issued_at(handler, name, source.as_ref()) {
+                Some(issued) if issued + age <= SystemTime::now() => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+/// Whether an assumed role ARN such as
+/// `arn:aws:sts::123456789012:assumed-role/admin/session` is a session of
+/// the role `arn:aws:iam::123456789012:role/path/admin`
+fn is_role_session(role_arn: &str, assumed_role_arn: &str) -> bool {
+    if role_arn == assumed_role_arn {
+        return true;
+    }
+    // Account and resource
+    let split = |arn: &'_ str| -> Option<(String, String)> {
+        let mut parts = arn.splitn(6, ':');
+        Some((parts.nth(4)?.to_owned(), parts.next()?.to_owned()))
+    };
+    let (Some((account, role)), Some((assumed_account, assumed_role))) =
+        (split(role_arn), split(assumed_role_arn))
+    else {
+        return false;
+    };
+    let role_name = role
+        .strip_prefix("role/")
+        .and_then(|role| role.rsplit('/').next());
+    let assumed_role_name = assumed_role
+        .strip_prefix("assumed-role/")
+        .and_then(|role| role.split('/').next());
+    account == assumed_account && role_name.is_some() && role_name == assumed_role_name
+}
+
+/// When the credentials were issued, worked out from the expiration and the
+/// recorded duration, or the default duration of the operation if none was
+/// given
+fn issued_at(
+    handler: &CredentialsHandler,
+    name: &str,
+    source: Option<&ProfileSource>,
+) -> Option<SystemTime> {
+    let source = source?;
+    let expiration = SystemTime::try_from(handler.get_short_term_profile(name)?.expiration).ok()?;
+    let duration = source.duration.unwrap_or(match source.kind {
+        ProfileKind::SessionToken => SessionToken::DEFAULT_DURATION,
+        ProfileKind::AssumeRole => AssumeRole::DEFAULT_DURATION,
+        ProfileKind::FederationToken => FederationToken::DEFAULT_DURATION,
+        ProfileKind::WebIdentity => WebIdentity::DEFAULT_DURATION,
+        ProfileKind::Saml => Saml::DEFAULT_DURATION,
+    });
+    expiration.checked_sub(Duration::from_secs(duration.try_into().ok()?))
 }
 
 impl Command for Clean {
     async fn exec(self, config: &Config) -> anyhow::Result<()> {
-        let mut creds_handler = CredentialsHandler::try_from(config)?;
+        let mut creds_handler = CredentialsHandler::try_from_locked(config)?;
         let sections =
             creds_handler.get_profiles_matching(|p| p.ends_with(&self.short_term_suffix));
         if sections.is_empty() {
             info!("No short-term profiles found");
             return Ok(());
         }
-        info!("Do you want to delete the following short-term profiles?");
-        for section in &sections {
-            println!("{}", section);
+        let sections = sections
+            .into_iter()
+            .filter(|section| self.matches(&creds_handler, section))
+            .collect::<Vec<_>>();
+        if sections.is_empty() {
+            info!("No short-term profiles match the filters");
+            return Ok(());
+        }
+        if self.dry_run {
+            info!("The following short-term profiles would be deleted:");
+            for section in &sections {
+                println!("{}", section);
+            }
+            return Ok(());
         }
-        if confirm_prompt("Confirm deletion") {
-            for section in sections {
-                creds_handler.ini.delete(Some(&section));
+        if !self.yes {
+            info!("Do you want to delete the following short-term profiles?");
+            for section in &sections {
+                println!("{}", section);
+            }
+            if !confirm_prompt("Confirm deletion") {
+                info!("Aborted deletion");
+                return Ok(());
             }
-            info!("Successfully deleted short-term profiles");
-            creds_handler.to_file()?;
-        } else {
-            info!("Aborted deletion");
         }
+        for section in &sections {
+            creds_handler.ini.delete(Some(section));
+        }
+        creds_handler.to_file()?;
+        info!("Successfully deleted short-term profiles");
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test_clean {
+    use super::*;
+
+    const CREDENTIALS: &str = r#"[dev]
+    aws_access_key_id = 1
+    aws_secret_access_key = 1
+    [dev-short-term]
+    aws_access_key_id = 1
+    aws_secret_access_key = 1
+    aws_session_token = 1
+    expiration = 2099-01-01T00:00:00Z
+    mfaws_kind = session-token
+    mfaws_source_profile = dev
+    [dev_1-role-team-admin-mfa-user_short-term]
+    aws_access_key_id = 1
+    aws_secret_access_key = 1
+    aws_session_token = 1
+    expiration = 2000-01-01T00:00:00Z
+    assumed_role_arn = arn:aws:iam::1:role/team/admin
+    mfaws_kind = assume-role
+    mfaws_source_profile = dev
+    mfaws_duration = 900
+    [dev_1-role-team-reader-mfa-user_short-term]
+    aws_access_key_id = 1
+    aws_secret_access_key = 1
+    aws_session_token = 1
+    expiration = 2099-01-01T00:00:00Z
+    assumed_role_arn = arn:aws:iam::1:role/team/reader
+    mfaws_kind = assume-role
+    mfaws_source_profile = reader
+    mfaws_long_term_profile = dev
+    [prod-short-term]
+    aws_access_key_id = 1
+    aws_secret_access_key = 1
+    aws_session_token = 1
+    expiration = 2000-01-01T00:00:00Z"#;
+
+    fn matching(clean: &Clean) -> Vec<String> {
+        let handler = CredentialsHandler::_new(CREDENTIALS).unwrap();
+        handler.get_profiles_matching(|p| p.ends_with("short-term") && clean.matches(&handler, p))
+    }
+
+    #[test]
+    fn filters_profiles() {
+        let all = matching(&Clean::default());
+        assert_eq!(all.len(), 4);
+
+        let expired = Clean {
+            expired: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            matching(&expired),
+            vec![
+                "dev_1-role-team-admin-mfa-user_short-term",
+                "prod-short-term"
+            ]
+        );
+
+        let dev = Clean {
+            source_profile: Some("dev".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            matching(&dev),
+            vec![
+                "dev-short-term",
+                "dev_1-role-team-admin-mfa-user_short-term",
+                "dev_1-role-team-reader-mfa-user_short-term"
+            ]
+        );
+        // The role profile itself has no long-term credentials
+        let reader = Clean {
+            source_profile: Some("reader".to_owned()),
+            ..Default::default()
+        };
+        assert!(matching(&reader).is_empty());
+
+        let role = Clean {
+            role_arn: Some("arn:aws:iam::1:role/team/admin".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            matching(&role),
+            vec!["dev_1-role-team-admin-mfa-user_short-term"]
+        );
+
+        // Filters are combined
+        let expired_dev = Clean {
+            source_profile: Some("dev".to_owned()),
+            ..expired
+        };
+        assert_eq!(
+            matching(&expired_dev),
+            vec!["dev_1-role-team-admin-mfa-user_short-term"]
+        );
+    }
+
+    #[test]
+    fn filters_by_age() {
+        // The legacy profile without a recorded source has no known age
+        let old = Clean {
+            older_than: Some(Duration::from_secs(86400)),
+            ..Default::default()
+        };
+        assert_eq!(
+            matching(&old),
+            vec!["dev_1-role-team-admin-mfa-user_short-term"]
+        );
+
+        let handler = CredentialsHandler::_new(CREDENTIALS).unwrap();
+        let name = "dev_1-role-team-admin-mfa-user_short-term";
+        let source = handler.get_profile_source(name);
+        let issued = issued_at(&handler, name, source.as_ref()).unwrap();
+        let expiration = handler.get_short_term_profile(name).unwrap().expiration;
+        assert_eq!(
+            issued + Duration::from_secs(900),
+            SystemTime::try_from(expiration).unwrap()
+        );
+    }
+
+    #[test]
+    fn matches_role_sessions() {
+        let assumed = "arn:aws:sts::1:assumed-role/admin/mfa-user";
+        assert!(is_role_session("arn:aws:iam::1:role/admin", assumed));
+        assert!(is_role_session("arn:aws:iam::1:role/path/admin", assumed));
+        assert!(is_role_session(assumed, assumed));
+        assert!(!is_role_session("arn:aws:iam::2:role/admin", assumed));
+        assert!(!is_role_session("arn:aws:iam::1:role/reader", assumed));
+        assert!(!is_role_session("arn:aws:iam::1:user/admin", assumed));
+        assert!(!is_role_session("admin", assumed));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{
+    aws_config::AwsConfigHandler,
+    cmds::Command,
+    config::Config,
+    mfaws_config::MfawsConfig,
+    sts::{assume_role::AssumeRole, session_token::SessionToken, ShortTermCredentials},
+};
+
+#[derive(clap::Args, Debug)]
+pub struct ConfigCommand {
+    #[command(subcommand)]
+    pub action: ConfigAction,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum ConfigAction {
+    #[clap(
+        about = "Print the effective settings after merging flags, the alias and the config files"
+    )]
+    Show(Show),
+}
+
+#[derive(clap::Args, Debug, Default)]
+#[command(
+    mut_arg("output", |arg| arg.hide(true)),
+    mut_arg("shell", |arg| arg.hide(true)),
+    mut_arg("otp", |arg| arg.hide(true)),
+    mut_arg("force_new_credentials", |arg| arg.hide(true))
+)]
+pub struct Show {
+    #[clap(flatten)]
+    pub role: AssumeRole,
+}
+
+impl Show {
+    fn print(key: &str, value: impl std::fmt::Display) {
+        println!("{:<20}{}", key, value);
+    }
+}
+
+impl Command for ConfigCommand {
+    async fn exec(self, config: &Config) -> anyhow::Result<()> {
+        match self.action {
+            ConfigAction::Show(cmd) => cmd.exec(config).await,
+        }
+    }
+}
+
+impl Command for Show {
+    async fn exec(mut self, config: &Config) -> anyhow::Result<()> {
+        let aws_config = AwsConfigHandler::try_from(config)?;
+        let mfaws_config = MfawsConfig::try_from(config)?;
+        self.role.apply_alias(&mfaws_config)?;
+        self.role.resolve(&aws_config)?;
+
+        Self::print("credentials_path", config.credentials_path.display());
+        Self::print("config_path", config.config_path.display());
+        Self::print("mfaws_config", config.mfaws_config_path.display());
+        Self::print(
+            "aliases",
+            mfaws_config
+                .aliases
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", "),
+        );
+        println!();
+
+        let (kind, default_duration, short_profile_name) = match self.role.role_arn {
+            Some(_) => (
+                "assume-role",
+                AssumeRole::DEFAULT_DURATION,
+                self.role.short_profile_name(),
+            ),
+            None => {
+                let cmd = SessionToken {
+                    config: self.role.config.clone(),
+                };
+                (
+                    "session-token",
+                    SessionToken::DEFAULT_DURATION,
+                    cmd.short_profile_name(),
+                )
+            }
+        };
+        let sts_config = &self.role.config;
+        Self::print("kind", kind);
+        Self::print("alias", self.role.alias.as_deref().unwrap_or_default());
+        Self::print("profile", &sts_config.profile_name);
+        if self.role.role_arn.is_some() {
+            Self::print("role_arn", self.role.role_arn());
+            Self::print("role_session_name", self.role.role_name());
+            Self::print(
+                "role_chain",
+                self.role
+                    .chain
+                    .iter()
+                    .map(|hop| hop.role_arn.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" -> "),
+            );
+            Self::print("policy_hash", self.role.policy_hash().unwrap_or_default());
+            Self::print("policy_arns", self.role.policy_arns.join(", "));
+            let session = &self.role.session;
+            Self::print(
+                "tags",
+                session
+                    .tags
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            );
+            Self::print(
+                "transitive_tag_keys",
+                session.transitive_tag_keys.join(", "),
+            );
+            Self::print(
+                "source_identity",
+                session.source_identity.as_deref().unwrap_or_default(),
+            );
+            Self::print(
+                "external_id",
+                session.external_id.as_deref().unwrap_or_default(),
+            );
+        }
+        Self::print(
+            "mfa_device",
+            sts_config.mfa_device.as_deref().unwrap_or_default(),
+        );
+        Self::print(
+            "otp_command",
+            sts_config.otp_command.as_deref().unwrap_or_default(),
+        );
+        match sts_config.duration {
+            Some(duration) => Self::print("duration", duration),
+            None => Self::print("duration", format!("{} (default)", default_duration)),
+        }
+        Self::print("sts_region", &sts_config.sts_region);
+        Self::print("short_term_suffix", &sts_config.short_term_suffix);
+        Self::print("short_term_profile", short_profile_name);
+        Ok(())
+    }
+}
@@ -0,0 +1,357 @@
+use std::{collections::HashMap, time::Duration};
+
+use crate::{
+    aws_config::AwsConfigHandler,
+    cmds::Command,
+    config::Config,
+    creds::CredentialsHandler,
+    profile::{Profile, ProfileKind, ProfileSource, ShortTermProfile},
+    sts::{
+        assume_role::AssumeRole, config::CommonStsConfig, request_st_profile,
+        session_options::SessionOptions, session_token::SessionToken,
+    },
+};
+
+#[derive(clap::Args, Debug, Default)]
+pub struct Daemon {
+    #[arg(
+        long = "refresh-margin",
+        default_value = "300",
+        help = "Renew short-term profiles that expire within this many seconds"
+    )]
+    pub refresh_margin: u64,
+    #[arg(
+        long,
+        default_value = "60",
+        help = "How often, in seconds, to check the short-term profiles"
+    )]
+    pub interval: u64,
+    #[arg(
+        long = "short-term-suffix",
+        default_value = "short-term",
+        help = "To identify the short-term credential profiles"
+    )]
+    pub short_term_suffix: String,
+    #[arg(
+        long,
+        default_value = "us-east-1",
+        help = "The STS region to use for profiles that do not record the region they were created with"
+    )]
+    pub sts_region: String,
+    #[arg(
+        long,
+        default_value = "false",
+        help = "Check the short-term profiles once and exit"
+    )]
+    pub once: bool,
+    /// Profiles that failed to renew, with their expiration at the time.
+    /// They are not tried again until the profile changes.
+    #[arg(skip)]
+    pub failed: HashMap<String, String>,
+}
+
+impl Daemon {
+    /// Short-term profiles written by mfaws that expire within the margin or
+    /// expired less than the margin ago, e.g. while the machine was asleep.
+    /// Profiles that expired earlier are considered abandoned. Session
+    /// tokens come first so that assumed roles can be renewed with a fresh
+    /// session token. Scoped-down sessions are skipped, only the hash of
+    /// their session policies is recorded.
+    fn expiring_profiles(
+        &self,
+        handler: &CredentialsHandler,
+        margin: Duration,
+    ) -> Vec<(String, ProfileSource, String)> {
+        let mut profiles = handler
+            .get_profiles_matching(|p| p.ends_with(&self.short_term_suffix))
+            .into_iter()
+            .filter_map(|name| {
+                let source = handler
+                    .get_profile_source(&name)
+                    .filter(|source| source.kind.is_renewable() && source.policy_hash.is_none())?;
+                let profile = handler.get_short_term_profile(&name)?;
+                let expiration = profile.format_expiration();
+                if self.failed.get(&name) == Some(&expiration) {
+                    return None;
+                }
+                (profile.expires_within(margin) && !profile.expired_before(margin))
+                    .then_some((name, source, expiration))
+            })
+            .collect::<Vec<_>>();
+        profiles.sort_by_key(|(_, source, _)| source.kind);
+        profiles
+    }
+
+    async fn renew_expiring(&mut self, config: &Config) -> anyhow::Result<()> {
+        let handler = CredentialsHandler::try_from(config)?;
+        let aws_config = AwsConfigHandler::try_from(config)?;
+        let margin = Duration::from_secs(self.refresh_margin);
+
+        for (name, source, expiration) in self.expiring_profiles(&handler, margin) {
+            info!("Renewing short-term profile \"{}\"", name);
+            match self.renew(config, &aws_config, &name, &source).await {
+                Ok(expiration) => {
+                    info!("Renewed \"{}\", valid until {}", name, expiration);
+                    self.failed.remove(&name);
+                }
+                Err(err) => {
+                    warn!(
+                        "Failed to renew \"{}\", not trying again until it changes: {}",
+                        name, err
+                    );
+                    self.failed.insert(name, expiration);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn sts_config(&self, source: &ProfileSource) -> CommonStsConfig {
+        CommonStsConfig {
+            profile_name: source.profile.clone(),
+            mfa_device: source.mfa_device.clone(),
+            otp_command: source.otp_command.clone(),
+            duration: source.duration,
+            short_term_suffix: self.short_term_suffix.clone(),
+            sts_region: source
+                .sts_region
+                .clone()
+                .unwrap_or_else(|| self.sts_region.clone()),
+            ..Default::default()
+        }
+    }
+
+    /// Rebuilds the command that assumed the role of a short-term profile.
+    /// If the recorded profile is a role profile for that same role, the
+    /// role comes from the AWS config file like it did originally, or it
+    /// would be assumed twice.
+    fn assume_role_cmd(
+        &self,
+        handler: &CredentialsHandler,
+        aws_config: &AwsConfigHandler,
+        name: &str,
+        source: &ProfileSource,
+    ) -> anyhow::Result<AssumeRole> {
+        let profile_role_arn = aws_config
+            .get_profile(&source.profile)?
+            .and_then(|profile| profile.role_arn);
+        let role_arn = handler
+            .get_profile(name)
+            .and_then(|section| section.get(ShortTermProfile::ASSUMED_ROLE_ARN))
+            .map(|arn| arn.to_owned())
+            .filter(|arn| profile_role_arn.as_ref() != Some(arn));
+        let mut cmd = AssumeRole {
+            role_arn,
+            role_name: source.role_session_name.clone(),
+            session: SessionOptions {
+                tags: source.tags.clone().into_iter().collect(),
+                transitive_tag_keys: source.transitive_tag_keys.clone(),
+                source_identity: source.source_identity.clone(),
+                external_id: source.external_id.clone(),
+            },
+            config: self.sts_config(source),
+            ..Default::default()
+        };
+        cmd.init(aws_config)?;
+        Ok(cmd)
+    }
+
+    /// Gets new credentials for a short-term profile and returns their
+    /// expiration. Assumed roles are renewed with the session token of their
+    /// source profile if it is still valid, everything else needs MFA.
+    async fn renew(
+        &self,
+        config: &Config,
+        aws_config: &AwsConfigHandler,
+        name: &str,
+        source: &ProfileSource,
+    ) -> anyhow::Result<String> {
+        // Re-read the credentials file, other profiles might have been
+        // renewed in the meantime
+        let mut handler = CredentialsHandler::try_from_locked(config)?;
+
+        let (st_profile, source) = match source.kind {
+            ProfileKind::SessionToken => {
+                let mut cmd = SessionToken {
+                    config: self.sts_config(source),
+                };
+                cmd.config.init(aws_config)?;
+                let (st_profile, source) = request_st_profile(&cmd, &handler).await?;
+                (st_profile.into_owned(), source)
+            }
+            ProfileKind::AssumeRole => {
+                let cmd = self.assume_role_cmd(&handler, aws_config, name, source)?;
+                let (st_profile, source) = request_st_profile(&cmd, &handler).await?;
+                (st_profile.into_owned(), source)
+            }
+            kind => anyhow::bail!("Profiles of kind \"{}\" cannot be renewed", kind.as_str()),
+        };
+
+        handler.set_short_term_profile(&st_profile, name);
+        handler.set_profile_source(&source, name);
+        handler.to_file()?;
+        Ok(st_profile.format_expiration())
+    }
+}
+
+impl Command for Daemon {
+    async fn exec(mut self, config: &Config) -> anyhow::Result<()> {
+        let interval = Duration::from_secs(self.interval);
+        info!(
+            "Renewing short-term profiles that expire within {}s",
+            self.refresh_margin
+        );
+        loop {
+            if self.once {
+                return self.renew_expiring(config).await;
+            }
+            // The files might be fixed by the next check, keep running
+            if let Err(err) = self.renew_expiring(config).await {
+                error!("Failed to check the short-term profiles: {}", err);
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_daemon {
+    use super::*;
+    use crate::sts::ShortTermCredentials;
+
+    #[test]
+    fn selects_expiring_profiles() {
+        let format = |time: std::time::SystemTime| {
+            aws_smithy_types::DateTime::from(time)
+                .fmt(aws_smithy_types::date_time::Format::DateTime)
+                .unwrap()
+        };
+        let in_a_minute = format(std::time::SystemTime::now() + Duration::from_secs(60));
+        let a_minute_ago = format(std::time::SystemTime::now() - Duration::from_secs(60));
+        let ini = format!(
+            r#"[dev]
+            aws_access_key_id = 1
+            [dev_role_short-term]
+            aws_access_key_id = 1
+            aws_secret_access_key = 1
+            aws_session_token = 1
+            expiration = {in_a_minute}
+            mfaws_kind = assume-role
+            mfaws_source_profile = dev
+            [dev-short-term]
+            aws_access_key_id = 1
+            aws_secret_access_key = 1
+            aws_session_token = 1
+            expiration = {in_a_minute}
+            mfaws_kind = session-token
+            mfaws_source_profile = dev
+            [expired-short-term]
+            aws_access_key_id = 1
+            aws_secret_access_key = 1
+            aws_session_token = 1
+            expiration = 2000-01-01T00:00:00Z
+            mfaws_kind = session-token
+            mfaws_source_profile = expired
+            [asleep-short-term]
+            aws_access_key_id = 1
+            aws_secret_access_key = 1
+            aws_session_token = 1
+            expiration = {a_minute_ago}
+            mfaws_kind = session-token
+            mfaws_source_profile = asleep
+            [legacy-short-term]
+            aws_access_key_id = 1
+            aws_secret_access_key = 1
+            aws_session_token = 1
+            expiration = {in_a_minute}
+            [valid-short-term]
+            aws_access_key_id = 1
+            aws_secret_access_key = 1
+            aws_session_token = 1
+            expiration = 2099-01-01T00:00:00Z
+            mfaws_kind = session-token
+            mfaws_source_profile = valid
+            [dev_scoped_short-term]
+            aws_access_key_id = 1
+            aws_secret_access_key = 1
+            aws_session_token = 1
+            expiration = {in_a_minute}
+            mfaws_kind = assume-role
+            mfaws_source_profile = dev
+            mfaws_policy_hash = 1a2b3c4d"#
+        );
+        let handler = CredentialsHandler::_new(&ini).unwrap();
+        let mut daemon = Daemon {
+            short_term_suffix: "short-term".to_owned(),
+            ..Default::default()
+        };
+        let names = |daemon: &Daemon| {
+            daemon
+                .expiring_profiles(&handler, Duration::from_secs(300))
+                .into_iter()
+                .map(|(name, ..)| name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(&daemon),
+            vec!["dev-short-term", "asleep-short-term", "dev_role_short-term"]
+        );
+
+        // Profiles that failed to renew are skipped until they change
+        daemon
+            .failed
+            .insert("asleep-short-term".to_owned(), a_minute_ago);
+        daemon.failed.insert(
+            "dev-short-term".to_owned(),
+            "2000-01-01T00:00:00Z".to_owned(),
+        );
+        assert_eq!(
+            names(&daemon),
+            vec!["dev-short-term", "dev_role_short-term"]
+        );
+    }
+
+    #[test]
+    fn renews_role_profile() {
+        let aws_config = AwsConfigHandler::_new(
+            r#"[profile admin]
+            role_arn = arn:aws:iam::123456789012:role/admin
+            source_profile = dev
+            duration_seconds = 7200"#,
+        )
+        .unwrap();
+        let handler = CredentialsHandler::_new(
+            r#"[dev]
+            aws_access_key_id = 1
+            aws_secret_access_key = 1
+            [dev_123456789012-role-admin-mfa-user_short-term]
+            aws_access_key_id = 1
+            aws_secret_access_key = 1
+            aws_session_token = 1
+            expiration = 2000-01-01T00:00:00Z
+            assumed_role_arn = arn:aws:iam::123456789012:role/admin
+            mfaws_kind = assume-role
+            mfaws_source_profile = admin
+            mfaws_role_session_name = mfa-user
+            mfaws_duration = 7200"#,
+        )
+        .unwrap();
+        let daemon = Daemon {
+            short_term_suffix: "short-term".to_owned(),
+            ..Default::default()
+        };
+        let name = "dev_123456789012-role-admin-mfa-user_short-term";
+        let source = handler.get_profile_source(name).unwrap();
+        let cmd = daemon
+            .assume_role_cmd(&handler, &aws_config, name, &source)
+            .unwrap();
+
+        assert_eq!(
+            cmd.hops().collect::<Vec<_>>(),
+            vec![("arn:aws:iam::123456789012:role/admin", "mfa-user", None)]
+        );
+        assert_eq!(cmd.config.profile_name, "dev");
+        assert_eq!(cmd.config.duration, Some(7200));
+        assert_eq!(cmd.short_profile_name(), name);
+    }
+}
@@ -0,0 +1,41 @@
+use crate::{cmds::Command, config::Config, permissions::find_all_issues};
+
+#[derive(clap::Args, Debug, Default)]
+pub struct Doctor {
+    #[arg(long, help = "Remove the permissions other users have")]
+    pub fix: bool,
+}
+
+impl Command for Doctor {
+    async fn exec(self, config: &Config) -> anyhow::Result<()> {
+        if cfg!(not(unix)) {
+            info!("File permissions are only checked on Unix");
+            return Ok(());
+        }
+        let issues = find_all_issues(&config.credentials_path);
+        if issues.is_empty() {
+            info!("No problems found");
+            return Ok(());
+        }
+        if !self.fix {
+            for issue in &issues {
+                warn!("{}", issue);
+            }
+            let found = match issues.len() {
+                1 => "Found 1 permission problem".to_string(),
+                n => format!("Found {} permission problems", n),
+            };
+            anyhow::bail!("{}, run `mfaws doctor --fix` to fix them", found);
+        }
+        for issue in issues {
+            issue
+                .fix()
+                .map_err(|e| anyhow::anyhow!("Failed to fix {:?}: {}", issue.path, e))?;
+            info!(
+                "Changed the mode of {:?} from {:o} to {:o}",
+                issue.path, issue.mode, issue.fixed_mode
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+use std::process::ExitStatus;
+
+use crate::{
+    aws_config::AwsConfigHandler,
+    cmds::Command,
+    config::Config,
+    creds::CredentialsHandler,
+    mfaws_config::MfawsConfig,
+    sts::{assume_role::AssumeRole, fetch_st_profile, session_token::SessionToken},
+};
+
+#[derive(clap::Args, Debug, Default)]
+#[command(
+    mut_arg("alias", |arg| arg.long("alias")),
+    mut_arg("output", |arg| arg.hide(true)),
+    mut_arg("shell", |arg| arg.hide(true))
+)]
+pub struct Exec {
+    #[clap(flatten)]
+    pub role: AssumeRole,
+    #[arg(
+        long = "no-write",
+        default_value = "false",
+        help = "Do not write new short-term credentials to the credentials file"
+    )]
+    pub no_write: bool,
+    #[arg(
+        required = true,
+        trailing_var_arg = true,
+        allow_hyphen_values = true,
+        value_name = "COMMAND",
+        help = "The command to run, followed by its arguments"
+    )]
+    pub command: Vec<String>,
+}
+
+impl Command for Exec {
+    async fn exec(mut self, config: &Config) -> anyhow::Result<()> {
+        let aws_config = AwsConfigHandler::try_from(config)?;
+        self.role.apply_alias(&MfawsConfig::try_from(config)?)?;
+        self.role.resolve(&aws_config)?;
+        let mut creds_handler = CredentialsHandler::try_from(config)?;
+
+        // Without a role, the credentials are a session token for the profile
+        let env_vars = match self.role.role_arn {
+            Some(_) => fetch_st_profile(&self.role, &mut creds_handler, !self.no_write)
+                .await?
+                .env_vars(),
+            None => {
+                let cmd = SessionToken {
+                    config: self.role.config,
+                };
+                fetch_st_profile(&cmd, &mut creds_handler, !self.no_write)
+                    .await?
+                    .env_vars()
+            }
+        };
+
+        let status = run_command(&self.command, env_vars).await?;
+        std::process::exit(exit_code(status));
+    }
+}
+
+async fn run_command(
+    command: &[String],
+    env_vars: impl IntoIterator<Item = (&'static str, String)>,
+) -> anyhow::Result<ExitStatus> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("No command to run"))?;
+    tokio::process::Command::new(program)
+        .args(args)
+        .envs(env_vars)
+        .status()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to run \"{}\": {}", program, e))
+}
+
+/// The exit code of the command. Like shells, a command killed by a signal
+/// exits with 128 plus the signal number.
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(1)
+}
+
+#[cfg(test)]
+mod test_exec {
+    use super::*;
+    use crate::profile::ShortTermProfile;
+
+    #[cfg(unix)]
+    fn sh(script: &str) -> Vec<String> {
+        vec!["sh".to_owned(), "-c".to_owned(), script.to_owned()]
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn passes_exit_code_through() {
+        let status = run_command(&sh("exit 3"), []).await.unwrap();
+        assert_eq!(exit_code(status), 3);
+        let status = run_command(&sh("kill -TERM $$"), []).await.unwrap();
+        assert_eq!(exit_code(status), 128 + 15);
+        assert!(run_command(&["mfaws-does-not-exist".to_owned()], [])
+            .await
+            .is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn injects_credentials() {
+        let env_vars = ShortTermProfile {
+            access_key: "access".to_owned(),
+            secret_key: "secret".to_owned(),
+            session_token: "token".to_owned(),
+            ..Default::default()
+        }
+        .env_vars();
+        let status = run_command(
+            &sh(r#"[ "$AWS_ACCESS_KEY_ID" = access ] && [ "$AWS_SECRET_ACCESS_KEY" = secret ] && [ "$AWS_SESSION_TOKEN" = token ]"#),
+            env_vars,
+        )
+        .await
+        .unwrap();
+        assert!(status.success());
+    }
+}
@@ -0,0 +1,17 @@
+use crate::{
+    aws_config::AwsConfigHandler,
+    cmds::Command,
+    config::Config,
+    creds::CredentialsHandler,
+    sts::{federation_token::FederationToken, get_st_profile},
+};
+
+impl Command for FederationToken {
+    async fn exec(mut self, config: &Config) -> anyhow::Result<()> {
+        let aws_config = AwsConfigHandler::try_from(config)?;
+        self.config.init(&aws_config)?;
+        self.init()?;
+        let creds_handler = CredentialsHandler::try_from(config)?;
+        get_st_profile(self, creds_handler).await
+    }
+}
@@ -1,22 +1,408 @@
-use crate::{cmds::Command, config::Config, creds::CredentialsHandler};
+use std::io::IsTerminal;
+
+use ini::Properties;
+
+use crate::{
+    cmds::Command,
+    config::Config,
+    creds::CredentialsHandler,
+    profile::{Profile, ShortTermProfile},
+};
 
 #[derive(clap::Args, Debug, Default)]
-pub struct List;
+pub struct List {
+    #[arg(
+        long = "short-term-suffix",
+        default_value = "short-term",
+        help = "To identify the short-term credential profiles"
+    )]
+    pub short_term_suffix: String,
+    #[arg(long, help = "List expired short-term profiles")]
+    pub expired: bool,
+    #[arg(long, help = "List short-term profiles that have not expired")]
+    pub valid: bool,
+    #[arg(long = "long-term", help = "List long-term profiles")]
+    pub long_term: bool,
+    #[arg(long, value_enum, default_value_t, help = "How to print the profiles")]
+    pub output: ListOutput,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ListOutput {
+    #[default]
+    Table,
+    Json,
+    Plain,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ProfileType {
+    LongTerm,
+    /// Temporary credentials with an expiration, written by mfaws
+    ShortTerm,
+    /// Anything else, e.g. temporary credentials written by other tools
+    Foreign,
+}
+
+/// A section of the credentials file
+#[derive(Debug, Default, PartialEq)]
+struct Entry {
+    name: String,
+    profile_type: Option<ProfileType>,
+    kind: Option<&'static str>,
+    expiration: Option<String>,
+    remaining_time: Option<String>,
+    expired: bool,
+    role_arn: Option<String>,
+    source_profile: Option<String>,
+    parent: Option<String>,
+    policy_hash: Option<String>,
+}
+
+impl ProfileType {
+    /// Temporary credentials are only short-term profiles if mfaws wrote
+    /// them, i.e. they record how they were created or have the short-term
+    /// suffix
+    fn of(name: &str, section: &Properties, short_term_suffix: &str) -> Self {
+        let has = |key| section.contains_key(key);
+        let by_mfaws = has(ShortTermProfile::SOURCE_KIND) || name.ends_with(short_term_suffix);
+        match (
+            has(ShortTermProfile::ACCESS_KEY) && has(ShortTermProfile::SECRET_KEY),
+            has(ShortTermProfile::SESSION_TOKEN),
+            has(ShortTermProfile::EXPIRATION),
+        ) {
+            (true, false, _) => ProfileType::LongTerm,
+            (true, true, true) if by_mfaws => ProfileType::ShortTerm,
+            _ => ProfileType::Foreign,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            ProfileType::LongTerm => "long-term",
+            ProfileType::ShortTerm => "short-term",
+            ProfileType::Foreign => "foreign",
+        }
+    }
+}
+
+impl Entry {
+    fn new(handler: &CredentialsHandler, name: &str, short_term_suffix: &str) -> Self {
+        let Some(section) = handler.get_profile(name) else {
+            return Self::default();
+        };
+        let profile_type = ProfileType::of(name, section, short_term_suffix);
+        let mut entry = Entry {
+            name: name.to_owned(),
+            profile_type: Some(profile_type),
+            ..Default::default()
+        };
+        if profile_type != ProfileType::ShortTerm {
+            return entry;
+        }
+
+        let expiration = section.get(ShortTermProfile::EXPIRATION);
+        entry.expiration = expiration.map(|e| e.to_owned());
+        entry.remaining_time = handler.is_profile_still_valid(name);
+        entry.expired = entry.remaining_time.is_none();
+        entry.role_arn = section
+            .get(ShortTermProfile::ASSUMED_ROLE_ARN)
+            .or(section.get(ShortTermProfile::FEDERATED_USER_ARN))
+            .map(|arn| arn.to_owned());
+        if let Some(source) = handler.get_profile_source(name) {
+            entry.kind = Some(source.kind.as_str());
+            entry.source_profile = Some(source.profile);
+            entry.parent = source.parent;
+            entry.policy_hash = source.policy_hash;
+        }
+        entry
+    }
+
+    fn is_long_term(&self) -> bool {
+        self.profile_type == Some(ProfileType::LongTerm)
+    }
+
+    fn is_short_term(&self) -> bool {
+        self.profile_type == Some(ProfileType::ShortTerm)
+    }
+
+    /// The profile the entry was derived from: the session token that signed
+    /// the request, or else the profile it was requested for
+    fn derived_from(&self) -> Option<&str> {
+        self.parent.as_deref().or(self.source_profile.as_deref())
+    }
+
+    fn cells(&self) -> [String; 7] {
+        let cell = |value: Option<&str>| value.unwrap_or("-").to_owned();
+        let remaining = match self.is_short_term() {
+            true if self.expired => Some("expired"),
+            true => self.remaining_time.as_deref(),
+            false => None,
+        };
+        let mut kind = cell(self.kind);
+        if let Some(ref hash) = self.policy_hash {
+            kind = format!("{} (policy {})", kind, hash);
+        }
+        [
+            self.name.clone(),
+            cell(self.profile_type.map(|t| t.as_str())),
+            kind,
+            cell(self.expiration.as_deref()),
+            cell(remaining),
+            cell(self.role_arn.as_deref()),
+            cell(self.derived_from()),
+        ]
+    }
+
+    fn json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "Name": self.name,
+            "Type": self.profile_type.map(|t| t.as_str()),
+            "Kind": self.kind,
+            "Expiration": self.expiration,
+            "RemainingTime": self.remaining_time,
+            "Expired": self.is_short_term().then_some(self.expired),
+            "RoleArn": self.role_arn,
+            "SourceProfile": self.source_profile,
+            "ParentProfile": self.parent,
+            "PolicyHash": self.policy_hash,
+        })
+    }
+}
+
+impl List {
+    /// Without filters, every profile is listed. Filters are combined.
+    fn matches(&self, entry: &Entry) -> bool {
+        if !self.expired && !self.valid && !self.long_term {
+            return true;
+        }
+        (self.expired && entry.is_short_term() && entry.expired)
+            || (self.valid && entry.is_short_term() && !entry.expired)
+            || (self.long_term && entry.is_long_term())
+    }
+
+    fn entries(&self, handler: &CredentialsHandler) -> Vec<Entry> {
+        handler
+            .ini
+            .sections()
+            .flatten()
+            .map(|name| Entry::new(handler, name, &self.short_term_suffix))
+            .filter(|entry| self.matches(entry))
+            .collect()
+    }
+}
+
+/// Aligns the columns. Expired profiles are printed in red if stdout is a
+/// terminal.
+fn table(entries: &[Entry], color: bool) -> String {
+    const HEADER: [&str; 7] = [
+        "NAME",
+        "TYPE",
+        "KIND",
+        "EXPIRATION",
+        "REMAINING",
+        "ROLE",
+        "PARENT",
+    ];
+    let rows = entries.iter().map(Entry::cells).collect::<Vec<_>>();
+    let mut widths = HEADER.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let format_row = |cells: &[String]| {
+        cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_owned()
+    };
+
+    let header = HEADER.map(|h| h.to_owned());
+    let mut lines = vec![format_row(&header)];
+    for (entry, row) in entries.iter().zip(&rows) {
+        let line = format_row(row);
+        lines.push(match entry.expired && color {
+            true => format!("\x1b[31m{}\x1b[0m", line),
+            false => line,
+        });
+    }
+    lines.join("\n")
+}
 
 impl Command for List {
     async fn exec(self, config: &Config) -> anyhow::Result<()> {
         let creds_handler = CredentialsHandler::try_from(config)?;
-        let sections = creds_handler.ini.sections().flatten().collect::<Vec<_>>();
-        let info = match sections.len() {
-            0 => format!("No AWS credential profiles found"),
-            1 => format!("Found 1 AWS credential profile:"),
-            _ => format!("Found {} AWS credential profiles:", sections.len()),
+        let entries = self.entries(&creds_handler);
+
+        match self.output {
+            ListOutput::Table => {
+                let info = match entries.len() {
+                    0 => "No AWS credential profiles found".to_string(),
+                    1 => "Found 1 AWS credential profile:".to_string(),
+                    _ => format!("Found {} AWS credential profiles:", entries.len()),
+                };
+                info!("{info}");
+                if !entries.is_empty() {
+                    println!("{}", table(&entries, std::io::stdout().is_terminal()));
+                }
+            }
+            ListOutput::Json => {
+                let entries = entries.iter().map(Entry::json).collect::<Vec<_>>();
+                println!("{}", serde_json::Value::Array(entries));
+            }
+            ListOutput::Plain => {
+                for entry in entries {
+                    println!("{}", entry.name);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_list {
+    use super::*;
+
+    const CREDENTIALS: &str = r#"[dev]
+    aws_access_key_id = 1
+    aws_secret_access_key = 1
+    [dev-short-term]
+    aws_access_key_id = 1
+    aws_secret_access_key = 1
+    aws_session_token = 1
+    expiration = 2099-01-01T00:00:00Z
+    mfaws_kind = session-token
+    mfaws_source_profile = dev
+    [dev_1-role-admin-mfa-user_short-term]
+    aws_access_key_id = 1
+    aws_secret_access_key = 1
+    aws_session_token = 1
+    expiration = 2000-01-01T00:00:00Z
+    assumed_role_arn = arn:aws:iam::1:role/admin
+    mfaws_kind = assume-role
+    mfaws_source_profile = dev
+    mfaws_parent_profile = dev-short-term
+    [sso]
+    sso_session = company
+    [vault]
+    aws_access_key_id = 1
+    aws_secret_access_key = 1
+    aws_session_token = 1
+    expiration = 2099-01-01T00:00:00Z"#;
+
+    fn default_list() -> List {
+        List {
+            short_term_suffix: "short-term".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn names(list: &List, handler: &CredentialsHandler) -> Vec<String> {
+        list.entries(handler).into_iter().map(|e| e.name).collect()
+    }
+
+    #[test]
+    fn classifies_profiles() {
+        let handler = CredentialsHandler::_new(CREDENTIALS).unwrap();
+        let entries = default_list().entries(&handler);
+        let types = entries
+            .iter()
+            .map(|e| e.profile_type.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            vec![
+                ProfileType::LongTerm,
+                ProfileType::ShortTerm,
+                ProfileType::ShortTerm,
+                ProfileType::Foreign,
+                ProfileType::Foreign
+            ]
+        );
+
+        let role = &entries[2];
+        assert!(role.expired);
+        assert_eq!(role.kind, Some("assume-role"));
+        assert_eq!(role.role_arn.as_deref(), Some("arn:aws:iam::1:role/admin"));
+        assert_eq!(role.derived_from(), Some("dev-short-term"));
+        assert!(!entries[1].expired);
+        assert_eq!(entries[1].derived_from(), Some("dev"));
+
+        // Temporary credentials written by other tools
+        let vault = &entries[4];
+        assert_eq!(vault.name, "vault");
+        assert!(vault.expiration.is_none());
+    }
+
+    #[test]
+    fn classifies_short_term_profiles_by_suffix() {
+        let handler = CredentialsHandler::_new(
+            r#"[legacy-short-term]
+            aws_access_key_id = 1
+            aws_secret_access_key = 1
+            aws_session_token = 1
+            expiration = 2099-01-01T00:00:00Z"#,
+        )
+        .unwrap();
+        let entries = default_list().entries(&handler);
+        assert_eq!(entries[0].profile_type, Some(ProfileType::ShortTerm));
+    }
+
+    #[test]
+    fn filters_profiles() {
+        let handler = CredentialsHandler::_new(CREDENTIALS).unwrap();
+        let list = List {
+            expired: true,
+            ..default_list()
         };
-        info!("{info}");
-        for section in sections {
-            println!("{}", section);
+        assert_eq!(
+            names(&list, &handler),
+            vec!["dev_1-role-admin-mfa-user_short-term"]
+        );
+        let list = List {
+            valid: true,
+            long_term: true,
+            ..default_list()
+        };
+        assert_eq!(names(&list, &handler), vec!["dev", "dev-short-term"]);
+    }
+
+    #[test]
+    fn prints_table() {
+        let handler = CredentialsHandler::_new(CREDENTIALS).unwrap();
+        let entries = List {
+            long_term: true,
+            expired: true,
+            ..default_list()
         }
+        .entries(&handler);
+        let lines = table(&entries, true);
+        let lines = lines.lines().collect::<Vec<_>>();
+        assert!(lines[0].starts_with("NAME"));
+        assert_eq!(
+            lines[1].split_whitespace().collect::<Vec<_>>(),
+            vec!["dev", "long-term", "-", "-", "-", "-", "-"]
+        );
+        assert!(lines[2].starts_with("\x1b[31mdev_1-role-admin-mfa-user_short-term"));
+        assert!(lines[2].contains("expired"));
 
-        Ok(())
+        assert!(!table(&entries, false).contains('\x1b'));
+    }
+
+    #[test]
+    fn prints_json() {
+        let handler = CredentialsHandler::_new(CREDENTIALS).unwrap();
+        let entries = default_list().entries(&handler);
+        let json = entries[2].json();
+        assert_eq!(json["Type"], "short-term");
+        assert_eq!(json["Expired"], true);
+        assert_eq!(json["ParentProfile"], "dev-short-term");
+        assert!(entries[0].json()["Expired"].is_null());
     }
 }
@@ -1,13 +1,30 @@
 use crate::{
-    cmds::{clean::Clean, list::List},
+    cmds::{
+        batch::Batch, clean::Clean, config::ConfigCommand, daemon::Daemon, doctor::Doctor,
+        exec::Exec, list::List, restore::Restore, serve::Serve, whoami::Whoami,
+    },
     config::Config,
-    sts::{assume_role::AssumeRole, session_token::SessionToken},
+    sts::{
+        assume_role::AssumeRole, config::CommonStsConfig, federation_token::FederationToken,
+        saml::Saml, session_token::SessionToken, web_identity::WebIdentity,
+    },
 };
 
 pub mod assume_role;
+pub mod batch;
 pub mod clean;
+pub mod config;
+pub mod daemon;
+pub mod doctor;
+pub mod exec;
+pub mod federation_token;
 pub mod list;
+pub mod restore;
+pub mod saml;
+pub mod serve;
 pub mod session_token;
+pub mod web_identity;
+pub mod whoami;
 
 pub trait Command {
     async fn exec(self, config: &Config) -> anyhow::Result<()>;
@@ -26,8 +43,76 @@ pub enum SubCommand {
         about = "Temporary credentials for an AWS IAM user"
     )]
     GetSessionToken(SessionToken),
+    #[clap(
+        name = "federation-token",
+        about = "Temporary credentials for a federated user"
+    )]
+    GetFederationToken(FederationToken),
+    #[clap(
+        name = "assume-role-with-web-identity",
+        about = "Temporary credentials for an AWS IAM Role from an OIDC token"
+    )]
+    AssumeRoleWithWebIdentity(WebIdentity),
+    #[clap(
+        name = "assume-role-with-saml",
+        about = "Temporary credentials for an AWS IAM Role from a SAML assertion"
+    )]
+    AssumeRoleWithSaml(Saml),
+    #[clap(about = "Temporary credentials for many AWS IAM Roles from a single MFA code")]
+    Batch(Batch),
+    #[clap(about = "Run a command with temporary credentials in its environment")]
+    Exec(Exec),
+    #[clap(about = "Serve temporary credentials to the AWS SDKs over a local HTTP endpoint")]
+    Serve(Serve),
+    #[clap(about = "Renew short-term profiles in the background before they expire")]
+    Daemon(Daemon),
+    #[clap(about = "Inspect the mfaws configuration")]
+    Config(ConfigCommand),
+    #[clap(
+        visible_alias = "identity",
+        about = "Show the AWS identity a profile maps to"
+    )]
+    Whoami(Whoami),
     #[clap(about = "Remove short-time profiles from your credentials file")]
     Clean(Clean),
+    #[clap(about = "Restore the credentials file from one of its backups")]
+    Restore(Restore),
+    #[clap(about = "Check who else can access your credentials file and its backups")]
+    Doctor(Doctor),
     #[clap(about = "List profiles in your credentials file")]
     List(List),
 }
+
+impl SubCommand {
+    pub fn reserves_stdout(&self) -> bool {
+        match self {
+            SubCommand::AssumeRole(cmd) => cmd.config.output.reserves_stdout(),
+            SubCommand::GetSessionToken(cmd) => cmd.config.output.reserves_stdout(),
+            SubCommand::GetFederationToken(cmd) => cmd.config.output.reserves_stdout(),
+            SubCommand::AssumeRoleWithWebIdentity(cmd) => cmd.config.output.reserves_stdout(),
+            SubCommand::AssumeRoleWithSaml(cmd) => cmd.config.output.reserves_stdout(),
+            SubCommand::Exec(_) => true,
+            SubCommand::Whoami(cmd) => cmd.output == whoami::WhoamiOutput::Json,
+            SubCommand::List(cmd) => cmd.output != list::ListOutput::Table,
+            _ => false,
+        }
+    }
+
+    /// The STS settings of the subcommand, if it has any
+    pub fn sts_config_mut(&mut self) -> Option<&mut CommonStsConfig> {
+        match self {
+            SubCommand::AssumeRole(cmd) => Some(&mut cmd.config),
+            SubCommand::GetSessionToken(cmd) => Some(&mut cmd.config),
+            SubCommand::GetFederationToken(cmd) => Some(&mut cmd.config),
+            SubCommand::AssumeRoleWithWebIdentity(cmd) => Some(&mut cmd.config),
+            SubCommand::AssumeRoleWithSaml(cmd) => Some(&mut cmd.config),
+            SubCommand::Batch(cmd) => Some(&mut cmd.config),
+            SubCommand::Exec(cmd) => Some(&mut cmd.role.config),
+            SubCommand::Serve(cmd) => Some(&mut cmd.role.config),
+            SubCommand::Config(cmd) => match cmd.action {
+                config::ConfigAction::Show(ref mut cmd) => Some(&mut cmd.role.config),
+            },
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use ini::Ini;
+
+use crate::{
+    backup::Backup,
+    cmds::Command,
+    config::Config,
+    creds::CredentialsHandler,
+    utils::{confirm_prompt, get_input},
+};
+
+#[derive(clap::Args, Debug, Default)]
+pub struct Restore {
+    #[arg(long, help = "Only list the backups")]
+    pub list: bool,
+    #[arg(
+        long,
+        value_name = "BACKUP",
+        help = "The backup to restore, by its number in the list or its timestamp. Asked for if omitted"
+    )]
+    pub backup: Option<String>,
+    #[arg(short, long, help = "Restore without asking for confirmation")]
+    pub yes: bool,
+}
+
+/// How restoring a backup changes a profile
+#[derive(Debug, PartialEq)]
+enum Change {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// Picks a backup by its 1-based number in the list or by its timestamp
+fn select<'a>(backups: &'a [Backup], selection: &str) -> anyhow::Result<&'a Backup> {
+    let selection = selection.trim();
+    let by_number = selection
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_sub(1))
+        .and_then(|i| backups.get(i));
+    by_number
+        .or_else(|| backups.iter().find(|b| b.timestamp == selection))
+        .ok_or_else(|| anyhow::anyhow!("No backup \"{}\"", selection))
+}
+
+/// The profiles that restoring the backup adds, removes or changes
+fn diff(current: &Ini, backup: &Ini) -> Vec<(Change, String)> {
+    let mut changes = Vec::new();
+    for (name, properties) in backup.iter() {
+        let Some(name) = name else {
+            continue;
+        };
+        match current.section(Some(name)) {
+            None => changes.push((Change::Added, name.to_owned())),
+            Some(existing) if existing != properties => {
+                changes.push((Change::Changed, name.to_owned()))
+            }
+            Some(_) => {}
+        }
+    }
+    for name in current.sections().flatten() {
+        if backup.section(Some(name)).is_none() {
+            changes.push((Change::Removed, name.to_owned()));
+        }
+    }
+    changes
+}
+
+fn load(backup: &Backup) -> anyhow::Result<(String, Ini)> {
+    let contents = std::fs::read_to_string(&backup.path)
+        .map_err(|e| anyhow::anyhow!("Failed to read backup {:?}: {}", backup.path, e))?;
+    let ini = Ini::load_from_str(contents.trim_start_matches('\u{feff}'))
+        .map_err(|e| anyhow::anyhow!("Failed to parse backup {:?}: {}", backup.path, e))?;
+    Ok((contents, ini))
+}
+
+fn print_backups(backups: &[Backup]) {
+    for (i, backup) in backups.iter().enumerate() {
+        let profiles = load(backup).map_or(0, |(_, ini)| ini.sections().flatten().count());
+        println!("{:>3}  {}  {} profiles", i + 1, backup.timestamp, profiles);
+    }
+}
+
+impl Command for Restore {
+    async fn exec(self, config: &Config) -> anyhow::Result<()> {
+        // The backups are most useful when the credentials file is gone
+        let mut creds_handler = CredentialsHandler::try_from_or_new(config)?;
+        let backups = creds_handler.list_backups()?;
+        if backups.is_empty() {
+            info!("No backups of the credentials file found");
+            return Ok(());
+        }
+
+        let backup = match self.backup {
+            Some(ref selection) if !self.list => select(&backups, selection)?,
+            _ => {
+                info!("Found {} backups of the credentials file:", backups.len());
+                print_backups(&backups);
+                if self.list {
+                    return Ok(());
+                }
+                let input = get_input(&format!(
+                    "Which backup do you want to restore? (1-{})",
+                    backups.len()
+                ))?;
+                select(&backups, &input)?
+            }
+        };
+
+        let (contents, backup_ini) = load(backup)?;
+        let changes = diff(&creds_handler.ini, &backup_ini);
+        if changes.is_empty() {
+            info!(
+                "Backup {} has the same profiles as the credentials file",
+                backup.timestamp
+            );
+        } else {
+            info!(
+                "Restoring backup {} changes these profiles:",
+                backup.timestamp
+            );
+            for (change, name) in &changes {
+                let marker = match change {
+                    Change::Added => '+',
+                    Change::Removed => '-',
+                    Change::Changed => '~',
+                };
+                println!("{} {}", marker, name);
+            }
+        }
+        if !self.yes && !confirm_prompt("Confirm restore") {
+            info!("Aborted restore");
+            return Ok(());
+        }
+        creds_handler.replace(&contents)?;
+        info!("Successfully restored backup {}", backup.timestamp);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_restore {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn backups() -> Vec<Backup> {
+        ["2024-05-02T10-00-00.000Z", "2024-05-01T10-00-00.000Z"]
+            .iter()
+            .map(|timestamp| Backup {
+                path: PathBuf::from(format!("credentials.{timestamp}")),
+                timestamp: timestamp.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn selects_backup() {
+        let backups = backups();
+        assert_eq!(select(&backups, "2").unwrap(), &backups[1]);
+        assert_eq!(select(&backups, " 1\n").unwrap(), &backups[0]);
+        assert_eq!(
+            select(&backups, "2024-05-01T10-00-00.000Z").unwrap(),
+            &backups[1]
+        );
+        assert!(select(&backups, "0").is_err());
+        assert!(select(&backups, "3").is_err());
+        assert!(select(&backups, "yesterday").is_err());
+    }
+
+    #[test]
+    fn diffs_profiles() {
+        let current = Ini::load_from_str("[dev]\nk = 1\n[prod]\nk = 1\n[new]\nk = 1").unwrap();
+        let backup = Ini::load_from_str("[dev]\nk = 1\n[prod]\nk = 2\n[old]\nk = 1").unwrap();
+        assert_eq!(
+            diff(&current, &backup),
+            vec![
+                (Change::Changed, "prod".to_owned()),
+                (Change::Added, "old".to_owned()),
+                (Change::Removed, "new".to_owned()),
+            ]
+        );
+    }
+}
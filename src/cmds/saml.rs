@@ -0,0 +1,19 @@
+use crate::{
+    aws_config::AwsConfigHandler,
+    cmds::Command,
+    config::Config,
+    creds::CredentialsHandler,
+    sts::{get_st_profile, saml::Saml},
+};
+
+impl Command for Saml {
+    async fn exec(mut self, config: &Config) -> anyhow::Result<()> {
+        let aws_config = AwsConfigHandler::try_from(config)?;
+        self.init(&aws_config)?;
+        if self.list_roles {
+            return self.print_roles();
+        }
+        let creds_handler = CredentialsHandler::try_from_or_new(config)?;
+        get_st_profile(self, creds_handler).await
+    }
+}
@@ -0,0 +1,168 @@
+use std::time::Duration;
+
+use tokio::net::TcpListener;
+
+use crate::{
+    aws_config::AwsConfigHandler,
+    cmds::Command,
+    config::Config,
+    creds::CredentialsHandler,
+    mfaws_config::MfawsConfig,
+    profile::ShortTermProfile,
+    server::{random_token, CredentialsServer, CredentialsSource, ServerMode},
+    sts::{
+        assume_role::AssumeRole, fetch_st_profile, session_token::SessionToken,
+        ShortTermCredentials,
+    },
+};
+
+#[derive(clap::Args, Debug, Default)]
+#[command(
+    mut_arg("alias", |arg| arg.long("alias")),
+    mut_arg("output", |arg| arg.hide(true))
+)]
+pub struct Serve {
+    #[clap(flatten)]
+    pub role: AssumeRole,
+    #[arg(
+        long,
+        default_value = "0",
+        help = "The port to listen on. A free port is picked if 0"
+    )]
+    pub port: u16,
+    #[arg(
+        long = "auth-token",
+        env = "AWS_CONTAINER_AUTHORIZATION_TOKEN",
+        hide_env_values = true,
+        help = "The token clients must send in the Authorization header. A random token is generated if omitted. Ignored with --imds"
+    )]
+    pub auth_token: Option<String>,
+    #[arg(
+        long,
+        default_value = "false",
+        help = "Emulate the EC2 instance metadata service (IMDSv2) instead of the container credentials endpoint"
+    )]
+    pub imds: bool,
+    #[arg(
+        long = "refresh-margin",
+        default_value = "300",
+        help = "Refresh the credentials when they expire within this many seconds"
+    )]
+    pub refresh_margin: u64,
+    #[arg(
+        long = "no-write",
+        default_value = "false",
+        help = "Do not write new short-term credentials to the credentials file"
+    )]
+    pub no_write: bool,
+}
+
+/// Gets credentials through the regular short-term profile flow, re-reading
+/// the credentials file every time.
+struct CommandSource<'a, T> {
+    cmd: T,
+    config: &'a Config,
+    write: bool,
+    force_next: bool,
+}
+
+impl<T> CredentialsSource for CommandSource<'_, T>
+where
+    T: ShortTermCredentials,
+{
+    async fn fetch(&mut self, force: bool) -> anyhow::Result<ShortTermProfile<'static>> {
+        let force = force || std::mem::take(&mut self.force_next);
+        self.cmd.config_mut().force_new_credentials = force;
+        let mut creds_handler = match self.write {
+            true => CredentialsHandler::try_from_locked(self.config)?,
+            false => CredentialsHandler::try_from(self.config)?,
+        };
+        let st_profile = fetch_st_profile(&self.cmd, &mut creds_handler, self.write)
+            .await?
+            .into_owned();
+        // A one-time password from the flags can only be used once, later
+        // refreshes prompt for a new one
+        self.cmd.config_mut().otp = None;
+        Ok(st_profile)
+    }
+
+    fn profile_name(&self) -> String {
+        self.cmd.short_profile_name()
+    }
+}
+
+impl Serve {
+    const AUTH_TOKEN_LENGTH: usize = 32;
+
+    async fn serve<T>(
+        cmd: T,
+        config: &Config,
+        write: bool,
+        listener: TcpListener,
+        mode: ServerMode,
+        refresh_margin: Duration,
+    ) -> anyhow::Result<()>
+    where
+        T: ShortTermCredentials,
+    {
+        let source = CommandSource {
+            force_next: cmd.config().force_new_credentials,
+            cmd,
+            config,
+            write,
+        };
+        CredentialsServer::new(source, mode, refresh_margin)
+            .run(listener)
+            .await
+    }
+}
+
+impl Command for Serve {
+    async fn exec(mut self, config: &Config) -> anyhow::Result<()> {
+        let aws_config = AwsConfigHandler::try_from(config)?;
+        self.role.apply_alias(&MfawsConfig::try_from(config)?)?;
+        self.role.resolve(&aws_config)?;
+
+        let listener = TcpListener::bind(("127.0.0.1", self.port)).await?;
+        let addr = listener.local_addr()?;
+        let refresh_margin = Duration::from_secs(self.refresh_margin);
+        let shell = self.role.config.shell;
+
+        let mode = if self.imds {
+            let endpoint = format!("http://{}", addr);
+            info!("Serving credentials on {} (IMDSv2)", endpoint);
+            println!(
+                "{}",
+                shell.export("AWS_EC2_METADATA_SERVICE_ENDPOINT", &endpoint)
+            );
+            ServerMode::Imds
+        } else {
+            let uri = format!("http://{}/", addr);
+            let auth_token = self
+                .auth_token
+                .unwrap_or_else(|| random_token(Self::AUTH_TOKEN_LENGTH));
+            info!("Serving credentials on {}", uri);
+            println!(
+                "{}",
+                shell.export("AWS_CONTAINER_CREDENTIALS_FULL_URI", &uri)
+            );
+            println!(
+                "{}",
+                shell.export("AWS_CONTAINER_AUTHORIZATION_TOKEN", &auth_token)
+            );
+            ServerMode::Container { auth_token }
+        };
+
+        // Without a role, the credentials are a session token for the profile
+        let write = !self.no_write;
+        match self.role.role_arn {
+            Some(_) => Self::serve(self.role, config, write, listener, mode, refresh_margin).await,
+            None => {
+                let cmd = SessionToken {
+                    config: self.role.config,
+                };
+                Self::serve(cmd, config, write, listener, mode, refresh_margin).await
+            }
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::{
+    aws_config::AwsConfigHandler,
     cmds::Command,
     config::Config,
     creds::CredentialsHandler,
@@ -7,7 +8,8 @@ use crate::{
 
 impl Command for SessionToken {
     async fn exec(mut self, config: &Config) -> anyhow::Result<()> {
-        self.config.init()?;
+        let aws_config = AwsConfigHandler::try_from(config)?;
+        self.config.init(&aws_config)?;
         let creds_handler = CredentialsHandler::try_from(config)?;
         get_st_profile(self, creds_handler).await
     }
@@ -0,0 +1,17 @@
+use crate::{
+    aws_config::AwsConfigHandler,
+    cmds::Command,
+    config::Config,
+    creds::CredentialsHandler,
+    sts::{get_st_profile, web_identity::WebIdentity},
+};
+
+impl Command for WebIdentity {
+    async fn exec(mut self, config: &Config) -> anyhow::Result<()> {
+        let aws_config = AwsConfigHandler::try_from(config)?;
+        self.init(&aws_config)?;
+        // CI runners and containers often have no credentials file yet
+        let creds_handler = CredentialsHandler::try_from_or_new(config)?;
+        get_st_profile(self, creds_handler).await
+    }
+}
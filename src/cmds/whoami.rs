@@ -0,0 +1,224 @@
+use std::time::Duration;
+
+use aws_sdk_sts::Client as STSClient;
+
+use crate::{
+    cmds::Command,
+    config::Config,
+    creds::CredentialsHandler,
+    sts::{config::CommonStsConfig, extract_sts_err},
+};
+
+#[derive(clap::Args, Debug, Default)]
+pub struct Whoami {
+    #[arg(
+        long = "profile",
+        env = "AWS_PROFILE",
+        default_value = "default",
+        help = "The long-term or short-term profile to check"
+    )]
+    pub profile_name: String,
+    #[arg(
+        long,
+        default_value = "us-east-1",
+        help = "The STS region to use for the AWS client"
+    )]
+    pub sts_region: String,
+    #[arg(long, value_enum, default_value_t, help = "How to print the identity")]
+    pub output: WhoamiOutput,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum WhoamiOutput {
+    #[default]
+    Table,
+    Json,
+}
+
+/// The identity a profile maps to, as returned by GetCallerIdentity
+#[derive(Debug, Default, PartialEq)]
+struct Identity {
+    profile: String,
+    account: String,
+    arn: String,
+    user_id: String,
+    /// Only set for short-term profiles
+    expiration: Option<String>,
+    remaining_time: Option<String>,
+}
+
+impl Identity {
+    fn table(&self) -> String {
+        let expiration = match (&self.expiration, &self.remaining_time) {
+            (Some(expiration), Some(remaining_time)) => {
+                format!("{} (valid for the next {})", expiration, remaining_time)
+            }
+            (Some(expiration), None) => expiration.clone(),
+            (None, _) => "never (long-term)".to_owned(),
+        };
+        [
+            ("Profile", self.profile.as_str()),
+            ("Account", &self.account),
+            ("Arn", &self.arn),
+            ("UserId", &self.user_id),
+            ("Expiration", &expiration),
+        ]
+        .iter()
+        .map(|(key, value)| format!("{:<12}{}", key, value))
+        .collect::<Vec<_>>()
+        .join("\n")
+    }
+
+    fn json(&self) -> String {
+        serde_json::json!({
+            "Profile": self.profile,
+            "Account": self.account,
+            "Arn": self.arn,
+            "UserId": self.user_id,
+            "Expiration": self.expiration,
+            "RemainingTime": self.remaining_time,
+        })
+        .to_string()
+    }
+}
+
+impl Whoami {
+    /// Short-term profiles are signed with their session token, everything
+    /// else is treated as a long-term profile.
+    async fn create_client(&self, handler: &CredentialsHandler) -> anyhow::Result<STSClient> {
+        if let Some(profile) = handler.get_short_term_profile(&self.profile_name) {
+            if profile.expires_within(Duration::ZERO) {
+                anyhow::bail!(
+                    "Short-term profile \"{}\" expired at {}",
+                    self.profile_name,
+                    profile.format_expiration()
+                );
+            }
+            return Ok(profile.create_client(self.sts_region.clone()).await);
+        }
+        let config = CommonStsConfig {
+            profile_name: self.profile_name.clone(),
+            ..Default::default()
+        };
+        let profile = handler.get_long_term_credentials(&config)?;
+        Ok(profile.create_client(self.sts_region.clone()).await)
+    }
+
+    #[cfg(not(feature = "e2e_test"))]
+    async fn get_caller_identity(client: STSClient) -> anyhow::Result<(String, String, String)> {
+        let output = client
+            .get_caller_identity()
+            .send()
+            .await
+            .map_err(extract_sts_err)?;
+        Ok((
+            output.account.unwrap_or_default(),
+            output.arn.unwrap_or_default(),
+            output.user_id.unwrap_or_default(),
+        ))
+    }
+
+    #[cfg(feature = "e2e_test")]
+    async fn get_caller_identity(client: STSClient) -> anyhow::Result<(String, String, String)> {
+        Ok((
+            "1111".to_owned(),
+            "arn:aws:iam::1111:user/user".to_owned(),
+            "AIDA1111".to_owned(),
+        ))
+    }
+}
+
+impl Command for Whoami {
+    async fn exec(self, config: &Config) -> anyhow::Result<()> {
+        let handler = CredentialsHandler::try_from(config)?;
+        let client = self.create_client(&handler).await?;
+        let (account, arn, user_id) = Self::get_caller_identity(client).await?;
+
+        let identity = Identity {
+            profile: self.profile_name.clone(),
+            account,
+            arn,
+            user_id,
+            expiration: handler
+                .get_short_term_profile(&self.profile_name)
+                .map(|profile| profile.format_expiration()),
+            remaining_time: handler.is_profile_still_valid(&self.profile_name),
+        };
+        match self.output {
+            WhoamiOutput::Table => println!("{}", identity.table()),
+            WhoamiOutput::Json => println!("{}", identity.json()),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_whoami {
+    use super::*;
+
+    fn identity() -> Identity {
+        Identity {
+            profile: "dev-short-term".to_owned(),
+            account: "123456789012".to_owned(),
+            arn: "arn:aws:iam::123456789012:user/jane".to_owned(),
+            user_id: "AIDAEXAMPLE".to_owned(),
+            expiration: Some("2099-01-01T00:00:00Z".to_owned()),
+            remaining_time: Some("1h".to_owned()),
+        }
+    }
+
+    #[test]
+    fn prints_table() {
+        assert_eq!(
+            identity().table(),
+            "Profile     dev-short-term\n\
+             Account     123456789012\n\
+             Arn         arn:aws:iam::123456789012:user/jane\n\
+             UserId      AIDAEXAMPLE\n\
+             Expiration  2099-01-01T00:00:00Z (valid for the next 1h)"
+        );
+        let long_term = Identity {
+            expiration: None,
+            remaining_time: None,
+            ..identity()
+        };
+        assert!(long_term.table().ends_with("Expiration  never (long-term)"));
+    }
+
+    #[test]
+    fn prints_json() {
+        let json = serde_json::from_str::<serde_json::Value>(&identity().json()).unwrap();
+        assert_eq!(json["Account"], "123456789012");
+        assert_eq!(json["RemainingTime"], "1h");
+
+        let long_term = Identity {
+            expiration: None,
+            ..identity()
+        };
+        let json = serde_json::from_str::<serde_json::Value>(&long_term.json()).unwrap();
+        assert!(json["Expiration"].is_null());
+    }
+
+    #[tokio::test]
+    async fn err_expired_profile() {
+        let handler = CredentialsHandler::_new(
+            r#"[dev-short-term]
+            aws_access_key_id = 1
+            aws_secret_access_key = 1
+            aws_session_token = 1
+            expiration = 2000-01-01T00:00:00Z"#,
+        )
+        .unwrap();
+        let cmd = Whoami {
+            profile_name: "dev-short-term".to_owned(),
+            ..Default::default()
+        };
+        assert!(cmd.create_client(&handler).await.is_err());
+
+        let cmd = Whoami {
+            profile_name: "missing".to_owned(),
+            ..Default::default()
+        };
+        assert!(cmd.create_client(&handler).await.is_err());
+    }
+}
@@ -1,23 +1,60 @@
 use std::path::PathBuf;
 
+use crate::permissions::PermissionPolicy;
+
 #[derive(clap::Parser, Debug, Default)]
 pub struct Config {
     #[arg(
         long,
         env = "AWS_SHARED_CREDENTIALS_FILE",
         global = true,
-        value_parser = valid_credentials_path,
+        value_parser = valid_path,
         default_value = ".aws/credentials",
         help = "Location of the AWS credentials file. Can be a relative path from your home directory or an absolute path to the file"
     )]
     pub credentials_path: PathBuf,
+    #[arg(
+        long,
+        env = "AWS_CONFIG_FILE",
+        global = true,
+        value_parser = valid_path,
+        default_value = ".aws/config",
+        help = "Location of the AWS config file. Can be a relative path from your home directory or an absolute path to the file"
+    )]
+    pub config_path: PathBuf,
+    #[arg(
+        long = "mfaws-config",
+        env = "MFAWS_CONFIG",
+        global = true,
+        value_parser = valid_path,
+        default_value = ".config/mfaws/config.toml",
+        help = "Location of the mfaws config file with role aliases. Can be a relative path from your home directory or an absolute path to the file"
+    )]
+    pub mfaws_config_path: PathBuf,
+    #[arg(
+        long,
+        env = "MFAWS_BACKUPS",
+        global = true,
+        default_value_t = 10,
+        help = "How many backups of the credentials file to keep. They are taken before each write. 0 turns backups off"
+    )]
+    pub backups: usize,
+    #[arg(
+        long,
+        env = "MFAWS_PERMISSIONS",
+        global = true,
+        value_enum,
+        default_value_t,
+        help = "What to do when the credentials file or its directory can be accessed by other users"
+    )]
+    pub permissions: PermissionPolicy,
 }
 
-fn valid_credentials_path(s: &str) -> Result<PathBuf, String> {
+fn valid_path(s: &str) -> Result<PathBuf, String> {
     let mut path = PathBuf::from(s);
     if path.is_relative() {
         path = dirs::home_dir()
-            .ok_or_else(|| format!("Cannot find home directory"))?
+            .ok_or_else(|| "Cannot find home directory".to_string())?
             .join(path.as_path());
     }
 
@@ -1,6 +1,8 @@
 use std::{
     borrow::Cow,
     fmt::Debug,
+    fs::File,
+    io::Write,
     path::{Path, PathBuf},
     time::SystemTime,
 };
@@ -9,8 +11,11 @@ use ini::{self, Ini, Properties};
 use thiserror::Error;
 
 use crate::{
+    backup::{Backup, Backups},
     config::Config,
-    profile::{DateTime, LongTermProfile, Profile, ShortTermProfile},
+    ini_edit::IniDocument,
+    permissions::{self, create_private_dir, private_file_options},
+    profile::{DateTime, LongTermProfile, Profile, ProfileSource, ShortTermProfile},
     sts::config::CommonStsConfig,
     utils::get_remaining_time,
 };
@@ -18,6 +23,13 @@ use crate::{
 pub struct CredentialsHandler {
     pub ini: Ini,
     path: Option<PathBuf>,
+    /// The file as it was loaded, to tell our changes apart from the ones
+    /// other processes made in the meantime
+    loaded: Ini,
+    /// How many backups to keep, none if 0
+    backups: usize,
+    /// Held from loading to writing the file, see `try_from_locked`
+    lock: Option<File>,
 }
 
 impl Debug for CredentialsHandler {
@@ -29,15 +41,23 @@ impl Debug for CredentialsHandler {
 impl TryFrom<&Config> for CredentialsHandler {
     type Error = anyhow::Error;
     fn try_from(config: &Config) -> Result<CredentialsHandler, Self::Error> {
-        CredentialsHandler::from_file(config.credentials_path.as_path())
+        permissions::check(&config.credentials_path, config.permissions)?;
+        Ok(
+            CredentialsHandler::from_file(config.credentials_path.as_path())?
+                .with_backups(config.backups),
+        )
     }
 }
 
 impl CredentialsHandler {
-    pub(self) fn _new(buf: &str) -> Result<Self, ini::ParseError> {
+    pub(crate) fn _new(buf: &str) -> Result<Self, ini::ParseError> {
+        let ini = Ini::load_from_str(buf)?;
         Ok(Self {
-            ini: Ini::load_from_str(buf)?,
+            loaded: ini.clone(),
+            ini,
             path: None,
+            backups: 0,
+            lock: None,
         })
     }
 
@@ -45,24 +65,185 @@ impl CredentialsHandler {
     where
         P: AsRef<Path>,
     {
+        let ini = Ini::load_from_file(path.as_ref())
+            .map_err(|e| anyhow::anyhow!("Failed to load credentials file: {}", e))?;
         Ok(Self {
-            ini: Ini::load_from_file(path.as_ref())
-                .map_err(|e| anyhow::anyhow!("Failed to load credentials file: {}", e))?,
+            loaded: ini.clone(),
+            ini,
             path: Some(path.as_ref().to_path_buf()),
+            backups: 0,
+            lock: None,
         })
     }
 
-    pub fn to_file(&self) -> anyhow::Result<()> {
-        match self.path {
-            Some(ref path) => self.ini.write_to_file(path)?,
-            None => anyhow::bail!("No path set"),
-        };
+    /// Like `from_file`, but takes the lock before the file is read and holds
+    /// it until the handler is dropped. No other invocation can change the
+    /// file in between, so concurrent renewals of the same profile do not
+    /// overwrite each other.
+    pub fn from_file_locked<P>(path: P) -> anyhow::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let lock = Self::lock(&resolve_path(path.as_ref()))?;
+        let mut handler = Self::from_file(path)?;
+        handler.lock = Some(lock);
+        Ok(handler)
+    }
+
+    /// Like `from_file`, but a missing credentials file is created on the
+    /// first write instead of being an error
+    pub fn from_file_or_new<P>(path: P) -> anyhow::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        if path.as_ref().exists() {
+            return Self::from_file(path);
+        }
+        Ok(Self {
+            ini: Ini::new(),
+            path: Some(path.as_ref().to_path_buf()),
+            loaded: Ini::new(),
+            backups: 0,
+            lock: None,
+        })
+    }
+
+    /// Like `try_from`, but a missing credentials file is created on the
+    /// first write instead of being an error
+    pub fn try_from_or_new(config: &Config) -> anyhow::Result<Self> {
+        if config.credentials_path.exists() {
+            return Self::try_from(config);
+        }
+        Ok(Self::from_file_or_new(config.credentials_path.as_path())?.with_backups(config.backups))
+    }
+
+    /// Like `try_from`, but holds the lock from loading to writing the file.
+    /// Used by the commands that write many profiles or run unattended
+    /// (batch, clean, daemon and serve). The other commands only lock while
+    /// writing and merge the changes made in the meantime, so that a prompt
+    /// for an MFA code does not block every other invocation.
+    pub fn try_from_locked(config: &Config) -> anyhow::Result<Self> {
+        permissions::check(&config.credentials_path, config.permissions)?;
+        Ok(
+            CredentialsHandler::from_file_locked(config.credentials_path.as_path())?
+                .with_backups(config.backups),
+        )
+    }
+
+    /// Keeps up to `backups` copies of the file, taken before each write
+    pub fn with_backups(mut self, backups: usize) -> Self {
+        self.backups = backups;
+        self
+    }
+
+    /// Writes the credentials file while holding an exclusive lock, so that
+    /// concurrent invocations do not lose each other's changes. If the file
+    /// changed since it was loaded, which can only happen if the lock was not
+    /// held since then, our changes are merged into it section by section. The file is replaced by renaming a temp file, so readers
+    /// never see a partially written file.
+    // Mutable because the merged file becomes the new state of the handler
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_file(&mut self) -> anyhow::Result<()> {
+        let path = self.resolved_path()?;
+        let _lock = self.lock_unless_held(&path)?;
+        let text = self.read_and_back_up(&path)?;
+
+        let on_disk = Ini::load_from_str(text.trim_start_matches('\u{feff}'))
+            .map_err(|e| anyhow::anyhow!("Failed to load credentials file: {}", e))?;
+        if !on_disk.iter().eq(self.loaded.iter()) {
+            debug!("The credentials file changed on disk, merging the changes");
+            let mut merged = on_disk.clone();
+            merge_changes(&self.loaded, &self.ini, &mut merged);
+            self.ini = merged;
+        }
+        // Only the sections that changed are rewritten, the rest of the file
+        // keeps its comments and formatting
+        let mut document = IniDocument::parse(&text);
+        document.update(&on_disk, &self.ini);
+        write_atomically(&path, &document.to_string())?;
+        self.loaded = self.ini.clone();
         Ok(())
     }
 
+    /// Replaces the whole file, e.g. with a backup. The current file is
+    /// backed up first, so this can be undone.
+    pub fn replace(&mut self, contents: &str) -> anyhow::Result<()> {
+        let ini = Ini::load_from_str(contents.trim_start_matches('\u{feff}'))
+            .map_err(|e| anyhow::anyhow!("Failed to parse credentials: {}", e))?;
+        let path = self.resolved_path()?;
+        let _lock = self.lock_unless_held(&path)?;
+        self.read_and_back_up(&path)?;
+        write_atomically(&path, contents)?;
+        self.loaded = ini.clone();
+        self.ini = ini;
+        Ok(())
+    }
+
+    /// The backups of the file, newest first
+    pub fn list_backups(&self) -> anyhow::Result<Vec<Backup>> {
+        Ok(Backups::for_file(&self.resolved_path()?).list())
+    }
+
+    /// Where the file is written to. A symlinked credentials file stays a
+    /// symlink.
+    fn resolved_path(&self) -> anyhow::Result<PathBuf> {
+        let Some(ref path) = self.path else {
+            anyhow::bail!("No path set");
+        };
+        Ok(resolve_path(path))
+    }
+
+    fn lock(path: &Path) -> anyhow::Result<File> {
+        if let Some(parent) = path.parent() {
+            create_private_dir(parent)?;
+        }
+        lock_file(path)
+    }
+
+    /// Locking again while the lock is held would wait forever
+    fn lock_unless_held(&self, path: &Path) -> anyhow::Result<Option<File>> {
+        match self.lock {
+            Some(_) => Ok(None),
+            None => Self::lock(path).map(Some),
+        }
+    }
+
+    /// Reads the file as it is on disk, backing it up before it is
+    /// overwritten. Must be called while holding the lock.
+    fn read_and_back_up(&self, path: &Path) -> anyhow::Result<String> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => anyhow::bail!("Failed to load credentials file: {}", e),
+        };
+        if self.backups > 0 && !text.is_empty() {
+            Backups::for_file(path)
+                .create(&text, self.backups)
+                .map_err(|e| anyhow::anyhow!("Failed to back up credentials file: {}", e))?;
+        }
+        Ok(text)
+    }
+
     pub fn get_long_term_profile<'a>(
         &'a self,
         conf: &'a CommonStsConfig,
+    ) -> Result<LongTermProfile<'a>, CredentialsError> {
+        self.read_long_term_profile(conf, true)
+    }
+
+    /// Like `get_long_term_profile`, but the profile does not need an MFA
+    /// device.
+    pub fn get_long_term_credentials<'a>(
+        &'a self,
+        conf: &'a CommonStsConfig,
+    ) -> Result<LongTermProfile<'a>, CredentialsError> {
+        self.read_long_term_profile(conf, false)
+    }
+
+    fn read_long_term_profile<'a>(
+        &'a self,
+        conf: &'a CommonStsConfig,
+        require_mfa_device: bool,
     ) -> Result<LongTermProfile<'a>, CredentialsError> {
         let profile = &conf.profile_name;
         let sections = self
@@ -93,8 +274,14 @@ impl CredentialsHandler {
                     .or(section.get(LongTermProfile::MFA_DEVICE))
                 {
                     Some(mfa_device) => pf.mfa_device = Cow::Borrowed(mfa_device),
-                    None => Err(CredentialsError::NoMfaDevice(profile.to_owned()))?,
+                    None if require_mfa_device => {
+                        Err(CredentialsError::NoMfaDevice(profile.to_owned()))?
+                    }
+                    None => {}
                 }
+                pf.totp_secret = section
+                    .get(LongTermProfile::MFA_TOTP_SECRET)
+                    .map(Cow::Borrowed);
 
                 Ok(pf)
             }
@@ -123,6 +310,125 @@ impl CredentialsHandler {
             .and_then(get_remaining_time)
     }
 
+    /// Reads a short-term profile previously written by
+    /// `set_short_term_profile`.
+    pub fn get_short_term_profile(&self, profile_name: &str) -> Option<ShortTermProfile<'static>> {
+        let section = self.get_profile(profile_name)?;
+        Some(ShortTermProfile {
+            access_key: section.get(ShortTermProfile::ACCESS_KEY)?.to_owned(),
+            secret_key: section.get(ShortTermProfile::SECRET_KEY)?.to_owned(),
+            session_token: section.get(ShortTermProfile::SESSION_TOKEN)?.to_owned(),
+            expiration: section.get(ShortTermProfile::EXPIRATION)?.parse().ok()?,
+            assumed_role_id: section
+                .get(ShortTermProfile::ASSUMED_ROLE_ID)
+                .map(|id| id.to_owned()),
+            assumed_role_arn: section
+                .get(ShortTermProfile::ASSUMED_ROLE_ARN)
+                .map(|arn| Cow::Owned(arn.to_owned())),
+            federated_user_arn: section
+                .get(ShortTermProfile::FEDERATED_USER_ARN)
+                .map(|arn| arn.to_owned()),
+            federated_user_id: section
+                .get(ShortTermProfile::FEDERATED_USER_ID)
+                .map(|id| id.to_owned()),
+        })
+    }
+
+    /// Reads how a short-term profile was created. Profiles written by older
+    /// versions have no source.
+    pub fn get_profile_source(&self, profile_name: &str) -> Option<ProfileSource> {
+        let section = self.get_profile(profile_name)?;
+        let get = |key: &str| section.get(key).map(|v| v.to_owned());
+        Some(ProfileSource {
+            kind: section.get(ShortTermProfile::SOURCE_KIND)?.parse().ok()?,
+            profile: get(ShortTermProfile::SOURCE_PROFILE)?,
+            long_term_profile: get(ShortTermProfile::SOURCE_LONG_TERM_PROFILE),
+            role_session_name: get(ShortTermProfile::SOURCE_ROLE_SESSION_NAME),
+            duration: section
+                .get(ShortTermProfile::SOURCE_DURATION)
+                .and_then(|d| d.parse().ok()),
+            parent: get(ShortTermProfile::SOURCE_PARENT),
+            policy_hash: get(ShortTermProfile::SOURCE_POLICY_HASH),
+            tags: section
+                .get(ShortTermProfile::SOURCE_TAGS)
+                .and_then(|tags| serde_json::from_str(tags).ok())
+                .unwrap_or_default(),
+            transitive_tag_keys: section
+                .get(ShortTermProfile::SOURCE_TRANSITIVE_TAG_KEYS)
+                .map(|keys| keys.split(',').map(|key| key.to_owned()).collect())
+                .unwrap_or_default(),
+            source_identity: get(ShortTermProfile::SOURCE_SOURCE_IDENTITY),
+            external_id: get(ShortTermProfile::SOURCE_EXTERNAL_ID),
+            mfa_device: get(ShortTermProfile::SOURCE_MFA_DEVICE),
+            otp_command: get(ShortTermProfile::SOURCE_OTP_COMMAND),
+            sts_region: get(ShortTermProfile::SOURCE_STS_REGION),
+        })
+    }
+
+    pub fn set_profile_source(&mut self, source: &ProfileSource, profile_name: &str) {
+        let optional = [
+            (
+                ShortTermProfile::SOURCE_LONG_TERM_PROFILE,
+                source.long_term_profile.clone(),
+            ),
+            (
+                ShortTermProfile::SOURCE_ROLE_SESSION_NAME,
+                source.role_session_name.clone(),
+            ),
+            (
+                ShortTermProfile::SOURCE_DURATION,
+                source.duration.map(|d| d.to_string()),
+            ),
+            (ShortTermProfile::SOURCE_PARENT, source.parent.clone()),
+            (
+                ShortTermProfile::SOURCE_POLICY_HASH,
+                source.policy_hash.clone(),
+            ),
+            (
+                ShortTermProfile::SOURCE_TAGS,
+                (!source.tags.is_empty())
+                    .then(|| serde_json::to_string(&source.tags).unwrap_or_default()),
+            ),
+            (
+                ShortTermProfile::SOURCE_TRANSITIVE_TAG_KEYS,
+                (!source.transitive_tag_keys.is_empty())
+                    .then(|| source.transitive_tag_keys.join(",")),
+            ),
+            (
+                ShortTermProfile::SOURCE_SOURCE_IDENTITY,
+                source.source_identity.clone(),
+            ),
+            (
+                ShortTermProfile::SOURCE_EXTERNAL_ID,
+                source.external_id.clone(),
+            ),
+            (
+                ShortTermProfile::SOURCE_MFA_DEVICE,
+                source.mfa_device.clone(),
+            ),
+            (
+                ShortTermProfile::SOURCE_OTP_COMMAND,
+                source.otp_command.clone(),
+            ),
+            (
+                ShortTermProfile::SOURCE_STS_REGION,
+                source.sts_region.clone(),
+            ),
+        ];
+        self.ini
+            .with_section(Some(profile_name))
+            .set(ShortTermProfile::SOURCE_KIND, source.kind.as_str())
+            .set(ShortTermProfile::SOURCE_PROFILE, source.profile.to_owned());
+        for (key, value) in optional {
+            match value {
+                Some(value) => self.ini.set_to(Some(profile_name), key.to_owned(), value),
+                None => {
+                    self.ini.delete_from(Some(profile_name), key);
+                }
+            }
+        }
+    }
+
     pub fn set_short_term_profile(&mut self, profile: &ShortTermProfile, profile_name: &str) {
         if let Some(ref arn) = &profile.assumed_role_arn {
             self.ini.set_to(
@@ -140,6 +446,22 @@ impl CredentialsHandler {
             );
         }
 
+        if let Some(ref arn) = profile.federated_user_arn {
+            self.ini.set_to(
+                Some(profile_name),
+                LongTermProfile::FEDERATED_USER_ARN.to_owned(),
+                arn.to_owned(),
+            );
+        }
+
+        if let Some(ref id) = profile.federated_user_id {
+            self.ini.set_to(
+                Some(profile_name),
+                LongTermProfile::FEDERATED_USER_ID.to_owned(),
+                id.to_owned(),
+            );
+        }
+
         self.ini
             .with_section(Some(profile_name))
             .set(LongTermProfile::EXPIRATION, profile.format_expiration())
@@ -152,6 +474,81 @@ impl CredentialsHandler {
     }
 }
 
+fn resolve_path(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Takes an exclusive advisory lock, released when the returned file is
+/// dropped. The credentials file itself is replaced on every write, so the
+/// lock is taken on a separate file next to it.
+fn lock_file(path: &Path) -> anyhow::Result<File> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    let lock_path = path.with_file_name(name);
+    let file = private_file_options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .map_err(|e| anyhow::anyhow!("Failed to open lock file {:?}: {}", lock_path, e))?;
+    file.lock()
+        .map_err(|e| anyhow::anyhow!("Failed to lock {:?}: {}", lock_path, e))?;
+    Ok(file)
+}
+
+/// Applies the changes from `loaded` to `ours` to `on_disk`: new and changed
+/// sections replace the ones on disk, deleted sections are removed. Sections
+/// we did not touch keep what other processes wrote to them.
+fn merge_changes(loaded: &Ini, ours: &Ini, on_disk: &mut Ini) {
+    for (name, properties) in ours.iter() {
+        if loaded.section(name) == Some(properties) {
+            continue;
+        }
+        match on_disk.section_mut(name) {
+            Some(section) => *section = properties.clone(),
+            None => {
+                on_disk
+                    .entry(name.map(|name| name.to_owned()))
+                    .or_insert(properties.clone());
+            }
+        }
+    }
+    for (name, _) in loaded.iter() {
+        if ours.section(name).is_none() {
+            on_disk.delete(name);
+        }
+    }
+}
+
+/// Writes to a temp file in the same directory and renames it over the
+/// credentials file, keeping its permissions. New files are only accessible
+/// by the owner.
+fn write_atomically(path: &Path, contents: &str) -> anyhow::Result<()> {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{}.tmp", std::process::id()));
+    let tmp_path = path.with_file_name(name);
+
+    let write = || -> std::io::Result<()> {
+        let mut file = private_file_options()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp_path)?;
+        if let Ok(metadata) = std::fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.write_all(contents.as_bytes())?;
+        file.flush()?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, path)
+    };
+    write().map_err(|e| {
+        let _ = std::fs::remove_file(&tmp_path);
+        anyhow::anyhow!("Failed to write credentials file {:?}: {}", path, e)
+    })
+}
+
 #[derive(Debug, Error, PartialEq)]
 pub enum CredentialsError {
     #[error("No access key found for \"{0}\"")]
@@ -249,6 +646,19 @@ mod test_long_term_profile {
         ));
     }
 
+    #[test]
+    fn credentials_without_mfa_device() {
+        let ini = r#"[test]
+        aws_access_key_id = 1
+        aws_secret_access_key = 1"#;
+        let handler = CredentialsHandler::_new(ini).unwrap();
+        let config = CommonStsConfig {
+            profile_name: "test".to_owned(),
+            ..Default::default()
+        };
+        assert!(handler.get_long_term_credentials(&config).is_ok());
+    }
+
     #[test]
     fn converts_successfully_1() {
         let ini = r#"[test]
@@ -276,13 +686,37 @@ mod test_long_term_profile {
         };
         assert!(handler.get_long_term_profile(&config).is_ok());
     }
+
+    #[test]
+    fn reads_totp_secret() {
+        let ini = r#"[test]
+        aws_access_key_id = 1
+        aws_secret_access_key = 1
+        aws_mfa_device = 2
+        aws_mfa_totp_secret = GEZDGNBV"#;
+        let handler = CredentialsHandler::_new(ini).unwrap();
+        let config = CommonStsConfig {
+            profile_name: "test".to_owned(),
+            ..Default::default()
+        };
+        assert_eq!(
+            handler
+                .get_long_term_profile(&config)
+                .unwrap()
+                .totp_secret
+                .as_deref(),
+            Some("GEZDGNBV")
+        );
+    }
 }
 
 #[cfg(test)]
 mod test_short_term_profile {
 
+    use std::collections::BTreeMap;
+
     use super::*;
-    use crate::profile::Profile;
+    use crate::profile::{Profile, ProfileKind};
 
     #[test]
     fn writes_st_profile_with_assumed_role() {
@@ -322,4 +756,286 @@ mod test_short_term_profile {
         assert!(section.contains_key(ShortTermProfile::SECRET_KEY));
         assert!(section.contains_key(ShortTermProfile::SESSION_TOKEN));
     }
+
+    #[test]
+    fn writes_st_profile_with_federated_user() {
+        let mut handler = CredentialsHandler::_new("").unwrap();
+        let profile = ShortTermProfile {
+            federated_user_arn: Some("arn".to_owned()),
+            federated_user_id: Some("id".to_owned()),
+            ..Default::default()
+        };
+        handler.set_short_term_profile(&profile, "test");
+        let read = handler.get_short_term_profile("test").unwrap();
+        assert_eq!(read.federated_user_arn.as_deref(), Some("arn"));
+        assert_eq!(read.federated_user_id.as_deref(), Some("id"));
+        assert!(read.assumed_role_arn.is_none());
+    }
+
+    #[test]
+    fn reads_st_profile() {
+        let mut handler = CredentialsHandler::_new("").unwrap();
+        let profile = ShortTermProfile {
+            access_key: "access".to_owned(),
+            secret_key: "secret".to_owned(),
+            session_token: "token".to_owned(),
+            assumed_role_arn: Some(Cow::Owned("arn".to_owned())),
+            ..Default::default()
+        };
+        handler.set_short_term_profile(&profile, "test");
+
+        let read = handler.get_short_term_profile("test").unwrap();
+        assert_eq!(read.access_key, "access");
+        assert_eq!(read.secret_key, "secret");
+        assert_eq!(read.session_token, "token");
+        assert_eq!(read.format_expiration(), profile.format_expiration());
+        assert_eq!(read.assumed_role_arn.as_deref(), Some("arn"));
+        assert!(read.assumed_role_id.is_none());
+        assert!(read.federated_user_arn.is_none());
+        assert!(handler.get_short_term_profile("missing").is_none());
+    }
+
+    #[test]
+    fn reads_and_writes_profile_source() {
+        let mut handler = CredentialsHandler::_new("").unwrap();
+        let source = ProfileSource {
+            kind: ProfileKind::AssumeRole,
+            profile: "admin".to_owned(),
+            long_term_profile: Some("dev".to_owned()),
+            role_session_name: Some("session".to_owned()),
+            duration: Some(900),
+            parent: Some("dev-short-term".to_owned()),
+            policy_hash: Some("1a2b3c4d".to_owned()),
+            tags: BTreeMap::from([
+                ("Project".to_owned(), "mfaws".to_owned()),
+                ("Cost=Center".to_owned(), "a b".to_owned()),
+            ]),
+            transitive_tag_keys: vec!["Project".to_owned(), "Cost=Center".to_owned()],
+            source_identity: Some("jane".to_owned()),
+            external_id: Some("third-party".to_owned()),
+            mfa_device: Some("arn:aws:iam::123456789012:mfa/user".to_owned()),
+            otp_command: Some("ykman oath accounts code --single aws".to_owned()),
+            sts_region: Some("eu-central-1".to_owned()),
+        };
+        handler.set_profile_source(&source, "test");
+        assert_eq!(handler.get_profile_source("test").unwrap(), source);
+
+        // Values that are no longer set are removed
+        let source = ProfileSource {
+            kind: ProfileKind::SessionToken,
+            profile: "dev".to_owned(),
+            ..Default::default()
+        };
+        handler.set_profile_source(&source, "test");
+        assert_eq!(handler.get_profile_source("test").unwrap(), source);
+        let section = handler.get_profile("test").unwrap();
+        assert!(!section.contains_key(ShortTermProfile::SOURCE_PARENT));
+        assert!(!section.contains_key(ShortTermProfile::SOURCE_POLICY_HASH));
+        assert!(!section.contains_key(ShortTermProfile::SOURCE_TAGS));
+    }
+
+    #[test]
+    fn ignores_profiles_without_source() {
+        let handler = CredentialsHandler::_new("[test]\nexpiration = 1").unwrap();
+        assert!(handler.get_profile_source("test").is_none());
+        assert!(handler.get_profile_source("missing").is_none());
+    }
+}
+
+#[cfg(test)]
+mod test_save {
+    use std::sync::{Arc, Barrier};
+
+    use super::*;
+
+    const CREDENTIALS: &str = "[dev]\naws_access_key_id = 1\naws_secret_access_key = 1\n";
+
+    fn setup() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("credentials");
+        std::fs::write(&path, CREDENTIALS).unwrap();
+        (dir, path)
+    }
+
+    fn sections(path: &Path) -> Vec<String> {
+        let ini = Ini::load_from_file(path).unwrap();
+        ini.sections().flatten().map(|s| s.to_owned()).collect()
+    }
+
+    #[test]
+    fn merges_changes_made_on_disk() {
+        let (_dir, path) = setup();
+        let mut first = CredentialsHandler::from_file(&path).unwrap();
+        let mut second = CredentialsHandler::from_file(&path).unwrap();
+
+        first.ini.with_section(Some("first")).set("key", "1");
+        first.to_file().unwrap();
+        second.ini.with_section(Some("second")).set("key", "2");
+        second.ini.delete(Some("dev"));
+        second.to_file().unwrap();
+
+        assert_eq!(sections(&path), vec!["first", "second"]);
+        // The handler sees the merged file
+        assert!(second.get_profile("first").is_some());
+    }
+
+    #[test]
+    fn keeps_sections_changed_by_others() {
+        let loaded = Ini::load_from_str("[a]\nk = 1\n[b]\nk = 1").unwrap();
+        let mut ours = loaded.clone();
+        ours.with_section(Some("a")).set("k", "ours");
+        let mut on_disk = Ini::load_from_str("[a]\nk = 2\n[b]\nk = 2\n[c]\nk = 2").unwrap();
+
+        merge_changes(&loaded, &ours, &mut on_disk);
+        assert_eq!(on_disk.get_from(Some("a"), "k"), Some("ours"));
+        assert_eq!(on_disk.get_from(Some("b"), "k"), Some("2"));
+        assert_eq!(on_disk.get_from(Some("c"), "k"), Some("2"));
+    }
+
+    #[test]
+    fn concurrent_writers() {
+        let (dir, path) = setup();
+        let writers = 8;
+        let barrier = Arc::new(Barrier::new(writers));
+        let handles = (0..writers)
+            .map(|i| {
+                let path = path.clone();
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    let mut handler = CredentialsHandler::from_file(&path).unwrap();
+                    // All writers start from the same file
+                    barrier.wait();
+                    handler
+                        .ini
+                        .with_section(Some(format!("profile-{i}")))
+                        .set("key", i.to_string());
+                    handler.to_file().unwrap();
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let mut written = sections(&path);
+        written.sort();
+        let mut expected = (0..writers)
+            .map(|i| format!("profile-{i}"))
+            .chain(["dev".to_owned()])
+            .collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(written, expected);
+
+        // Only the credentials file and its lock file are left
+        let mut files = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(files, vec!["credentials", "credentials.lock"]);
+    }
+
+    #[test]
+    fn locked_writers_do_not_lose_updates() {
+        let (_dir, path) = setup();
+        let writers = 8;
+        let handles = (0..writers)
+            .map(|_| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    let mut handler = CredentialsHandler::from_file_locked(&path).unwrap();
+                    let count = handler
+                        .ini
+                        .get_from(Some("counter"), "count")
+                        .map_or(0, |count| count.parse::<usize>().unwrap());
+                    handler
+                        .ini
+                        .with_section(Some("counter"))
+                        .set("count", (count + 1).to_string());
+                    handler.to_file().unwrap();
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let ini = Ini::load_from_file(&path).unwrap();
+        assert_eq!(
+            ini.get_from(Some("counter"), "count"),
+            Some(writers.to_string().as_str())
+        );
+    }
+
+    #[test]
+    fn keeps_comments_and_formatting() {
+        let (_dir, path) = setup();
+        let original = "# Hand-maintained\n[dev]\naws_access_key_id = 1 ; not a comment\n\n; Work\n[work]\n  aws_secret_access_key   =   2\n";
+        std::fs::write(&path, original).unwrap();
+
+        let mut handler = CredentialsHandler::from_file(&path).unwrap();
+        handler
+            .ini
+            .with_section(Some("dev-short-term"))
+            .set(ShortTermProfile::SESSION_TOKEN, "token");
+        handler.to_file().unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(written.starts_with(original));
+
+        handler.ini.delete(Some("dev-short-term"));
+        handler.to_file().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), original);
+    }
+
+    #[test]
+    fn backs_up_before_writing() {
+        let (_dir, path) = setup();
+        let mut handler = CredentialsHandler::from_file(&path)
+            .unwrap()
+            .with_backups(2);
+        handler.ini.with_section(Some("new")).set("key", "1");
+        handler.to_file().unwrap();
+        let backups = handler.list_backups().unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(
+            std::fs::read_to_string(&backups[0].path).unwrap(),
+            CREDENTIALS
+        );
+
+        // Restoring the backup backs up the current file first
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        handler.replace(CREDENTIALS).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), CREDENTIALS);
+        assert!(handler.get_profile("new").is_none());
+        assert_eq!(handler.list_backups().unwrap().len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (_dir, path) = setup();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        let mut handler = CredentialsHandler::from_file(&path).unwrap();
+        handler.ini.with_section(Some("new")).set("key", "1");
+        handler.to_file().unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn creates_private_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("aws").join("credentials");
+        let mut handler = CredentialsHandler::from_file_or_new(&path).unwrap();
+        handler.ini.with_section(Some("new")).set("key", "1");
+        handler.to_file().unwrap();
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&path), 0o600);
+        assert_eq!(mode(&dir.path().join("aws")), 0o700);
+    }
 }
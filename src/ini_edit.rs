@@ -0,0 +1,411 @@
+use std::fmt::{self, Display};
+
+use ini::{Ini, LineSeparator, Properties, WriteOption};
+
+const BOM: char = '\u{feff}';
+
+/// An INI file kept as its lines of text. It is updated by applying the
+/// differences between two parsed versions of the file, so only the
+/// sections and keys that changed are rewritten. Comments, blank lines,
+/// ordering and spacing of everything else stay byte-for-byte the same.
+#[derive(Debug, Default)]
+pub struct IniDocument {
+    bom: bool,
+    lines: Vec<Line>,
+    /// The line ending of new lines, taken from the file
+    newline: &'static str,
+}
+
+#[derive(Debug)]
+struct Line {
+    text: String,
+    /// `\n`, `\r\n`, or empty for a last line without a line ending
+    ending: &'static str,
+}
+
+enum LineKind<'a> {
+    Section(&'a str),
+    Property(&'a str),
+    Other,
+}
+
+/// The lines of a section, from its header to the next one
+struct Block {
+    header: usize,
+    end: usize,
+    last_property: Option<usize>,
+}
+
+impl Line {
+    /// Classifies the line the way rust-ini parses it: comments start with
+    /// `;` or `#` and keys end at the first `=` or `:`
+    fn kind(&self) -> LineKind<'_> {
+        let trimmed = self.text.trim();
+        if let Some(header) = trimmed.strip_prefix('[') {
+            return LineKind::Section(header.split(']').next().unwrap_or_default().trim());
+        }
+        if trimmed.starts_with([';', '#']) {
+            return LineKind::Other;
+        }
+        match self.text.find(['=', ':']) {
+            Some(index) => LineKind::Property(self.text[..index].trim()),
+            None => LineKind::Other,
+        }
+    }
+
+    fn is_blank(&self) -> bool {
+        self.text.trim().is_empty()
+    }
+
+    /// Everything up to the value: indentation, key, separator and the
+    /// spaces after it
+    fn value_start(&self) -> Option<usize> {
+        let separator = self.text.find(['=', ':'])?;
+        let value = &self.text[separator + 1..];
+        Some(self.text.len() - value.trim_start_matches([' ', '\t']).len())
+    }
+
+    fn set_value(&mut self, value: &str) {
+        if let Some(start) = self.value_start() {
+            self.text.truncate(start);
+            self.text.push_str(&escape(value));
+        }
+    }
+}
+
+impl IniDocument {
+    pub fn parse(text: &str) -> Self {
+        let bom = text.starts_with(BOM);
+        let text = text.trim_start_matches(BOM);
+        let lines = text
+            .split_inclusive('\n')
+            .map(|line| match line.strip_suffix("\r\n") {
+                Some(text) => (text, "\r\n"),
+                None => match line.strip_suffix('\n') {
+                    Some(text) => (text, "\n"),
+                    None => (line, ""),
+                },
+            })
+            .map(|(text, ending)| Line {
+                text: text.to_owned(),
+                ending,
+            })
+            .collect::<Vec<_>>();
+        let newline = match lines.first() {
+            Some(line) if line.ending == "\r\n" => "\r\n",
+            _ => "\n",
+        };
+        Self {
+            bom,
+            lines,
+            newline,
+        }
+    }
+
+    /// Rewrites the document, which reads as `current`, so that it reads as
+    /// `target`. Changed values keep the spacing around their `=`, new keys
+    /// and sections follow the style of the file. The general section is
+    /// left alone, mfaws never writes to it.
+    pub fn update(&mut self, current: &Ini, target: &Ini) {
+        for name in current.sections().flatten() {
+            if target.section(Some(name)).is_none() {
+                self.remove_sections(name);
+            }
+        }
+        for (name, properties) in target.iter() {
+            let Some(name) = name else {
+                continue;
+            };
+            match current.section(Some(name)) {
+                Some(existing) if existing == properties => {}
+                Some(existing) if self.find_block(name).is_some() => {
+                    self.update_section(name, existing, properties)
+                }
+                _ => self.append_section(name, properties),
+            }
+        }
+    }
+
+    fn find_block(&self, name: &str) -> Option<Block> {
+        let header = self.lines.iter().position(
+            |line| matches!(line.kind(), LineKind::Section(section) if same_name(section, name)),
+        )?;
+        let end = self.lines[header + 1..]
+            .iter()
+            .position(|line| matches!(line.kind(), LineKind::Section(_)))
+            .map_or(self.lines.len(), |offset| header + 1 + offset);
+        let last_property = (header + 1..end)
+            .rev()
+            .find(|&i| matches!(self.lines[i].kind(), LineKind::Property(_)));
+        Some(Block {
+            header,
+            end,
+            last_property,
+        })
+    }
+
+    /// Removes the header and properties of every section with the name.
+    /// Comments after the last property are kept, they may belong to the
+    /// next section.
+    fn remove_sections(&mut self, name: &str) {
+        while let Some(block) = self.find_block(name) {
+            let mut start = block.header;
+            let mut end = block.last_property.map_or(block.header + 1, |i| i + 1);
+            // Do not leave the blank line that separated the section behind
+            if start > 0 && self.lines[start - 1].is_blank() {
+                start -= 1;
+            } else if start == 0 && self.lines.get(end).is_some_and(Line::is_blank) {
+                end += 1;
+            }
+            let removed_ending = self.lines[end - 1].ending;
+            let at_end = end == self.lines.len();
+            self.lines.drain(start..end);
+            // The file keeps ending with or without a line ending
+            if at_end {
+                if let Some(last) = self.lines.last_mut() {
+                    last.ending = removed_ending;
+                }
+            }
+        }
+    }
+
+    fn update_section(&mut self, name: &str, existing: &Properties, properties: &Properties) {
+        let Some(block) = self.find_block(name) else {
+            return;
+        };
+        let mut seen = Vec::new();
+        let mut end = block.end;
+        let mut i = block.header + 1;
+        while i < end {
+            let key = match self.lines[i].kind() {
+                LineKind::Property(key) => key.to_owned(),
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            };
+            let is_duplicate = seen.iter().any(|k: &String| same_name(k, &key));
+            match properties.get(&key) {
+                Some(value) if !is_duplicate => {
+                    if existing.get(&key) != Some(value) {
+                        self.lines[i].set_value(value);
+                    }
+                    seen.push(key);
+                    i += 1;
+                }
+                _ => {
+                    self.lines.remove(i);
+                    end -= 1;
+                }
+            }
+        }
+
+        let (indent, separator) = self.property_style(Some(block.header));
+        let mut at = self
+            .find_block(name)
+            .and_then(|block| block.last_property)
+            .map_or(block.header + 1, |i| i + 1);
+        for (key, value) in properties.iter() {
+            if seen.iter().any(|k| same_name(k, key)) {
+                continue;
+            }
+            let text = format!("{}{}{}{}", indent, key, separator, escape(value));
+            self.insert_line(at, text);
+            seen.push(key.to_owned());
+            at += 1;
+        }
+    }
+
+    fn append_section(&mut self, name: &str, properties: &Properties) {
+        let (indent, separator) = self.property_style(None);
+        if self.lines.last().is_some_and(|line| !line.is_blank()) {
+            self.insert_line(self.lines.len(), String::new());
+        }
+        self.insert_line(self.lines.len(), format!("[{}]", name));
+        for (key, value) in properties.iter() {
+            let text = format!("{}{}{}{}", indent, key, separator, escape(value));
+            self.insert_line(self.lines.len(), text);
+        }
+    }
+
+    /// A new last line takes over the line ending of the previous last line,
+    /// so the file keeps ending with or without one
+    fn insert_line(&mut self, at: usize, text: String) {
+        let ending = match at == self.lines.len() {
+            true => match self.lines.last_mut() {
+                Some(last) => std::mem::replace(&mut last.ending, self.newline),
+                None => self.newline,
+            },
+            false => self.newline,
+        };
+        self.lines.insert(at, Line { text, ending });
+    }
+
+    /// The indentation and the separator, e.g. ` = `, of the first property
+    /// in the section, or else in the file. Defaults to rust-ini's style.
+    fn property_style(&self, header: Option<usize>) -> (String, String) {
+        let in_section = header.into_iter().flat_map(|header| {
+            self.lines[header + 1..]
+                .iter()
+                .take_while(|line| !matches!(line.kind(), LineKind::Section(_)))
+        });
+        let property = in_section
+            .chain(self.lines.iter())
+            .find(|line| matches!(line.kind(), LineKind::Property(_)));
+        let Some(line) = property else {
+            return (String::new(), "=".to_owned());
+        };
+        let text = &line.text;
+        let indent = &text[..text.len() - text.trim_start().len()];
+        let separator = text.find(['=', ':']).unwrap_or_default();
+        let key_end = text[..separator].trim_end().len();
+        let value_start = line.value_start().unwrap_or(text.len());
+        (indent.to_owned(), text[key_end..value_start].to_owned())
+    }
+}
+
+impl Display for IniDocument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.bom {
+            write!(f, "{}", BOM)?;
+        }
+        for line in &self.lines {
+            write!(f, "{}{}", line.text, line.ending)?;
+        }
+        Ok(())
+    }
+}
+
+/// Section names and keys are case insensitive
+fn same_name(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+/// Escapes a value the way rust-ini writes it, so that it reads back the same
+fn escape(value: &str) -> String {
+    let mut ini = Ini::new();
+    ini.with_general_section().set("k", value);
+    let mut buf = Vec::new();
+    let option = WriteOption {
+        line_separator: LineSeparator::CR,
+        ..Default::default()
+    };
+    if ini.write_to_opt(&mut buf, option).is_err() {
+        return value.to_owned();
+    }
+    let line = String::from_utf8_lossy(&buf);
+    line.trim_end_matches('\n')
+        .strip_prefix("k=")
+        .unwrap_or(value)
+        .to_owned()
+}
+
+#[cfg(test)]
+mod test_ini_edit {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../e2e/fixtures/credentials");
+
+    const HAND_MAINTAINED: &str = "; Managed by hand, keep the comments\r\n\
+        [default]\r\n\
+        aws_access_key_id     = AKIA1\r\n\
+        aws_secret_access_key = secret\r\n\
+        \r\n\
+        # Expires daily\r\n\
+        [default-short-term]\r\n\
+        aws_access_key_id     = ASIA1\r\n\
+        aws_secret_access_key = secret\r\n\
+        aws_session_token     = token\r\n\
+        expiration            = 2000-01-01T00:00:00Z\r\n\
+        \r\n\
+        [dev]\r\n\
+        aws_access_key_id     = AKIA2 \r\n";
+
+    fn update(text: &str, change: impl Fn(&mut Ini)) -> String {
+        let current = Ini::load_from_str(text).unwrap();
+        let mut target = current.clone();
+        change(&mut target);
+        let mut document = IniDocument::parse(text);
+        document.update(&current, &target);
+        let updated = document.to_string();
+        // The result reads as the target
+        let reread = Ini::load_from_str(&updated).unwrap();
+        assert!(reread.iter().eq(target.iter()), "{}", updated);
+        updated
+    }
+
+    #[test]
+    fn round_trips_fixture() {
+        assert_eq!(update(FIXTURE, |_| {}), FIXTURE);
+        assert_eq!(update(HAND_MAINTAINED, |_| {}), HAND_MAINTAINED);
+        assert_eq!(IniDocument::parse("").to_string(), "");
+    }
+
+    #[test]
+    fn adds_and_removes_sections() {
+        let added = update(FIXTURE, |ini| {
+            ini.with_section(Some("dev-short-term"))
+                .set("aws_access_key_id", "ASIA")
+                .set("aws_session_token", "a\\b");
+        });
+        assert!(added.starts_with(FIXTURE));
+        assert_eq!(
+            &added[FIXTURE.len()..],
+            "\n\n[dev-short-term]\naws_access_key_id=ASIA\naws_session_token=a\\\\b"
+        );
+
+        let removed = update(&added, |ini| {
+            ini.delete(Some("dev-short-term"));
+        });
+        assert_eq!(removed, FIXTURE);
+    }
+
+    #[test]
+    fn only_changes_keys_that_changed() {
+        let updated = update(HAND_MAINTAINED, |ini| {
+            ini.with_section(Some("default-short-term"))
+                .set("aws_session_token", "new-token")
+                .set("expiration", "2099-01-01T00:00:00Z")
+                .set("mfaws_kind", "session-token")
+                .delete(&"aws_secret_access_key");
+        });
+        let expected = HAND_MAINTAINED
+            .replace(
+                "aws_secret_access_key = secret\r\naws_session_token     = token",
+                "aws_session_token     = new-token",
+            )
+            .replace(
+                "2000-01-01T00:00:00Z\r\n",
+                "2099-01-01T00:00:00Z\r\nmfaws_kind     = session-token\r\n",
+            );
+        assert_eq!(updated, expected);
+    }
+
+    #[test]
+    fn removes_section_keeping_comments() {
+        let updated = update(HAND_MAINTAINED, |ini| {
+            ini.delete(Some("default-short-term"));
+        });
+        assert_eq!(
+            updated,
+            "; Managed by hand, keep the comments\r\n\
+             [default]\r\n\
+             aws_access_key_id     = AKIA1\r\n\
+             aws_secret_access_key = secret\r\n\
+             \r\n\
+             # Expires daily\r\n\
+             \r\n\
+             [dev]\r\n\
+             aws_access_key_id     = AKIA2 \r\n"
+        );
+    }
+
+    #[test]
+    fn writes_new_file() {
+        let updated = update("", |ini| {
+            ini.with_section(Some("a")).set("k", "1");
+            ini.with_section(Some("b")).set("k", "2");
+        });
+        assert_eq!(updated, "[a]\nk=1\n\n[b]\nk=2\n");
+    }
+}
@@ -1,10 +1,15 @@
 pub use simplelog::*;
 
-pub fn init() {
+/// Logs go to stderr only if stdout is reserved for the output of a command
+pub fn init(reserved_stdout: bool) {
+    let mode = match reserved_stdout {
+        true => TerminalMode::Stderr,
+        false => TerminalMode::Mixed,
+    };
     CombinedLogger::init(vec![TermLogger::new(
         LevelFilter::Info,
         ConfigBuilder::new().add_filter_allow_str("mfaws").build(), // suppress logging from other crates
-        TerminalMode::Mixed,
+        mode,
         ColorChoice::Auto,
     )])
     .unwrap();
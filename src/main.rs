@@ -2,26 +2,46 @@
     feature = "e2e_test",
     allow(dead_code, unused_imports, unused_variables)
 )]
+mod aws_config;
+mod backup;
 mod cli;
 mod cmds;
 mod config;
 mod creds;
+mod ini_edit;
 mod logger;
+mod mfaws_config;
+mod otp;
+mod output;
+mod permissions;
 mod profile;
+mod server;
 mod sts;
+mod totp;
 mod utils;
 
 use cmds::{Command, SubCommand};
+use config::Config;
 
 #[macro_use]
 extern crate log;
 
-async fn run() -> anyhow::Result<()> {
-    let (command, config) = cli::parse();
+async fn run(command: SubCommand, config: Config) -> anyhow::Result<()> {
     match command {
         SubCommand::AssumeRole(cmd) => cmd.exec(&config).await?,
         SubCommand::GetSessionToken(cmd) => cmd.exec(&config).await?,
+        SubCommand::GetFederationToken(cmd) => cmd.exec(&config).await?,
+        SubCommand::AssumeRoleWithWebIdentity(cmd) => cmd.exec(&config).await?,
+        SubCommand::AssumeRoleWithSaml(cmd) => cmd.exec(&config).await?,
+        SubCommand::Batch(cmd) => cmd.exec(&config).await?,
+        SubCommand::Exec(cmd) => cmd.exec(&config).await?,
+        SubCommand::Serve(cmd) => cmd.exec(&config).await?,
+        SubCommand::Daemon(cmd) => cmd.exec(&config).await?,
+        SubCommand::Config(cmd) => cmd.exec(&config).await?,
+        SubCommand::Whoami(cmd) => cmd.exec(&config).await?,
         SubCommand::Clean(cmd) => cmd.exec(&config).await?,
+        SubCommand::Restore(cmd) => cmd.exec(&config).await?,
+        SubCommand::Doctor(cmd) => cmd.exec(&config).await?,
         SubCommand::List(cmd) => cmd.exec(&config).await?,
     };
     Ok(())
@@ -29,8 +49,10 @@ async fn run() -> anyhow::Result<()> {
 
 #[tokio::main]
 async fn main() {
-    logger::init();
-    if let Err(err) = run().await {
+    let (command, config) = cli::parse();
+    logger::init(command.reserves_stdout());
+    if let Err(err) = run(command, config).await {
         error!("{}", err);
+        std::process::exit(1);
     }
 }
@@ -0,0 +1,140 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::config::Config;
+
+/// The mfaws configuration file (`~/.config/mfaws/config.toml`). Aliases are
+/// defined in `[aliases.<name>]` tables.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct MfawsConfig {
+    #[serde(default)]
+    pub aliases: BTreeMap<String, Alias>,
+}
+
+/// A named role. Values from flags and environment variables take precedence
+/// over the alias, which takes precedence over the AWS config file.
+#[derive(Debug, Default, Clone, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Alias {
+    pub role_arn: Option<String>,
+    pub role_session_name: Option<String>,
+    pub duration: Option<i32>,
+    pub source_profile: Option<String>,
+    pub region: Option<String>,
+    pub suffix: Option<String>,
+    /// An inline session policy, as a file path or JSON
+    pub policy: Option<String>,
+    #[serde(default)]
+    pub policy_arns: Vec<String>,
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    #[serde(default)]
+    pub transitive_tag_keys: Vec<String>,
+    pub source_identity: Option<String>,
+    pub external_id: Option<String>,
+}
+
+impl TryFrom<&Config> for MfawsConfig {
+    type Error = anyhow::Error;
+    fn try_from(config: &Config) -> Result<MfawsConfig, Self::Error> {
+        MfawsConfig::from_file(config.mfaws_config_path.as_path())
+    }
+}
+
+impl MfawsConfig {
+    pub(crate) fn _new(buf: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(buf)
+    }
+
+    /// The config file is optional. If it does not exist, an empty config is
+    /// used.
+    pub fn from_file<P>(path: P) -> anyhow::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        if !path.as_ref().exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path.as_ref())
+            .map_err(|e| anyhow::anyhow!("Failed to load mfaws config file: {}", e))?;
+        Self::_new(&content).map_err(|e| anyhow::anyhow!("Failed to load mfaws config file: {}", e))
+    }
+
+    pub fn get_alias(&self, name: &str) -> Result<&Alias, MfawsConfigError> {
+        self.aliases
+            .get(name)
+            .ok_or_else(|| MfawsConfigError::UnknownAlias(name.to_owned()))
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum MfawsConfigError {
+    #[error("Alias \"{0}\" not found in the mfaws config file")]
+    UnknownAlias(String),
+}
+
+#[cfg(test)]
+mod test_mfaws_config {
+    use super::*;
+
+    #[test]
+    fn reads_aliases() {
+        let config = MfawsConfig::_new(
+            r#"[aliases.prod-admin]
+            role_arn = "arn:aws:iam::123456789012:role/admin"
+            role_session_name = "session"
+            duration = 900
+            source_profile = "dev"
+            region = "eu-central-2"
+            suffix = "temp"
+            policy = '{"Version": "2012-10-17"}'
+            policy_arns = ["arn:aws:iam::aws:policy/ReadOnlyAccess"]
+            tags = { Project = "mfaws", CostCenter = "1234" }
+            transitive_tag_keys = ["Project"]
+            source_identity = "jane"
+            external_id = "third-party"
+
+            [aliases.readonly]
+            role_arn = "arn:aws:iam::123456789012:role/readonly""#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.get_alias("prod-admin").unwrap(),
+            &Alias {
+                role_arn: Some("arn:aws:iam::123456789012:role/admin".to_owned()),
+                role_session_name: Some("session".to_owned()),
+                duration: Some(900),
+                source_profile: Some("dev".to_owned()),
+                region: Some("eu-central-2".to_owned()),
+                suffix: Some("temp".to_owned()),
+                policy: Some(r#"{"Version": "2012-10-17"}"#.to_owned()),
+                policy_arns: vec!["arn:aws:iam::aws:policy/ReadOnlyAccess".to_owned()],
+                tags: BTreeMap::from([
+                    ("CostCenter".to_owned(), "1234".to_owned()),
+                    ("Project".to_owned(), "mfaws".to_owned()),
+                ]),
+                transitive_tag_keys: vec!["Project".to_owned()],
+                source_identity: Some("jane".to_owned()),
+                external_id: Some("third-party".to_owned()),
+            }
+        );
+        assert_eq!(config.get_alias("readonly").unwrap().duration, None);
+        assert_eq!(
+            config.get_alias("missing").unwrap_err(),
+            MfawsConfigError::UnknownAlias("missing".to_owned())
+        );
+    }
+
+    #[test]
+    fn empty_config() {
+        assert_eq!(MfawsConfig::_new("").unwrap(), MfawsConfig::default());
+    }
+
+    #[test]
+    fn err_unknown_key() {
+        assert!(MfawsConfig::_new("[aliases.dev]\nrole = \"arn\"").is_err());
+    }
+}
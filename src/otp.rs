@@ -0,0 +1,82 @@
+use std::process::Stdio;
+
+use thiserror::Error;
+
+/// Runs a command that prints a one-time password to stdout, such as
+/// `ykman oath accounts code -s aws` or `op item get aws --otp`. The command
+/// inherits stdin and stderr so it can interact with the user.
+pub async fn run_otp_command(command: &str) -> Result<String, OtpError> {
+    let mut process = match cfg!(windows) {
+        true => tokio::process::Command::new("cmd"),
+        false => tokio::process::Command::new("sh"),
+    };
+    let output = process
+        .arg(if cfg!(windows) { "/C" } else { "-c" })
+        .arg(command)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .await
+        .map_err(|e| OtpError::CommandNotRun(command.to_owned(), e.to_string()))?;
+
+    if !output.status.success() {
+        return Err(OtpError::CommandFailed(
+            command.to_owned(),
+            output.status.to_string(),
+        ));
+    }
+    validate_otp(String::from_utf8_lossy(&output.stdout).trim())
+}
+
+/// The output is not included in the error, it might be a secret
+pub fn validate_otp(otp: &str) -> Result<String, OtpError> {
+    match otp.len() == 6 && otp.chars().all(|c| c.is_ascii_digit()) {
+        true => Ok(otp.to_owned()),
+        false => Err(OtpError::InvalidOtp(otp.chars().count())),
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum OtpError {
+    #[error("Failed to run OTP command \"{0}\": {1}")]
+    CommandNotRun(String, String),
+    #[error("OTP command \"{0}\" failed with {1}")]
+    CommandFailed(String, String),
+    #[error("The OTP command did not print a 6-digit one-time password (got {0} characters)")]
+    InvalidOtp(usize),
+}
+
+#[cfg(test)]
+mod test_otp {
+    use super::*;
+
+    #[test]
+    fn validates_otp() {
+        assert_eq!(validate_otp("012345"), Ok("012345".to_owned()));
+        assert!(validate_otp("12345").is_err());
+        assert!(validate_otp("1234567").is_err());
+        assert!(validate_otp("12345a").is_err());
+        assert!(validate_otp("").is_err());
+        assert_eq!(
+            validate_otp("secret-token").unwrap_err().to_string(),
+            "The OTP command did not print a 6-digit one-time password (got 12 characters)"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn runs_otp_command() {
+        assert_eq!(
+            run_otp_command("printf '  123456\\n'").await,
+            Ok("123456".to_owned())
+        );
+        assert!(matches!(
+            run_otp_command("echo invalid").await,
+            Err(OtpError::InvalidOtp(_))
+        ));
+        assert!(matches!(
+            run_otp_command("exit 3").await,
+            Err(OtpError::CommandFailed(_, _))
+        ));
+    }
+}
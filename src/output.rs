@@ -0,0 +1,185 @@
+use crate::profile::ShortTermProfile;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputMode {
+    /// Write the short-term profile to the credentials file
+    #[default]
+    File,
+    /// Print the credentials as JSON for the AWS `credential_process`
+    /// setting instead of writing them to the credentials file
+    CredentialProcess,
+    /// Print shell statements that export the credentials instead of writing
+    /// them to the credentials file
+    Env,
+    /// Additionally print a shell statement that exports AWS_PROFILE
+    EnvProfile,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Shell {
+    #[default]
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+impl OutputMode {
+    /// Whether stdout is reserved for the output, in which case nothing else
+    /// may be printed to it
+    pub fn reserves_stdout(&self) -> bool {
+        !matches!(self, Self::File)
+    }
+
+    /// Whether new credentials are written to the credentials file. A valid
+    /// short-term profile that is already there is reused either way.
+    pub fn writes_profile(&self) -> bool {
+        matches!(self, Self::File | Self::EnvProfile)
+    }
+}
+
+impl Shell {
+    /// Quotes a value so that the shell treats it as a literal string
+    pub fn quote(&self, value: &str) -> String {
+        match self {
+            Shell::Bash | Shell::Zsh => format!("'{}'", value.replace('\'', r"'\''")),
+            Shell::Fish => format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'")),
+            // PowerShell also treats typographic single quotes as quotes
+            Shell::Powershell => format!(
+                "'{}'",
+                value.chars().fold(String::new(), |mut acc, c| {
+                    if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}') {
+                        acc.push(c);
+                    }
+                    acc.push(c);
+                    acc
+                })
+            ),
+        }
+    }
+
+    pub fn export(&self, key: &str, value: &str) -> String {
+        let value = self.quote(value);
+        match self {
+            Shell::Bash | Shell::Zsh => format!("export {}={}", key, value),
+            Shell::Fish => format!("set -gx {} {}", key, value),
+            Shell::Powershell => format!("$Env:{} = {}", key, value),
+        }
+    }
+}
+
+/// Formats the credentials as shell statements that export them as
+/// environment variables.
+pub fn env(profile: &ShortTermProfile, shell: Shell) -> String {
+    profile
+        .env_vars()
+        .iter()
+        .map(|(key, value)| shell.export(key, value))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Formats a shell statement that makes the AWS CLI and SDKs use a profile.
+pub fn env_profile(profile_name: &str, shell: Shell) -> String {
+    shell.export("AWS_PROFILE", profile_name)
+}
+
+/// Formats the credentials as expected by the AWS SDKs from a
+/// `credential_process`.
+pub fn credential_process(profile: &ShortTermProfile) -> String {
+    serde_json::json!({
+        "Version": 1,
+        "AccessKeyId": profile.access_key,
+        "SecretAccessKey": profile.secret_key,
+        "SessionToken": profile.session_token,
+        "Expiration": profile.format_expiration(),
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod test_output {
+    use super::*;
+
+    #[test]
+    fn writes_profile_only_when_it_is_used() {
+        assert!(OutputMode::File.writes_profile());
+        assert!(OutputMode::EnvProfile.writes_profile());
+        assert!(!OutputMode::CredentialProcess.writes_profile());
+        assert!(!OutputMode::Env.writes_profile());
+    }
+
+    #[test]
+    fn formats_credential_process() {
+        let profile = ShortTermProfile {
+            access_key: "access".to_owned(),
+            secret_key: "secret".to_owned(),
+            session_token: "token".to_owned(),
+            expiration: "2023-04-05T21:57:52Z".parse().unwrap(),
+            ..Default::default()
+        };
+        let json: serde_json::Value = serde_json::from_str(&credential_process(&profile)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "Version": 1,
+                "AccessKeyId": "access",
+                "SecretAccessKey": "secret",
+                "SessionToken": "token",
+                "Expiration": "2023-04-05T21:57:52Z",
+            })
+        );
+    }
+
+    #[test]
+    fn quotes_posix_shells() {
+        for shell in [Shell::Bash, Shell::Zsh] {
+            assert_eq!(shell.quote("a$b c"), "'a$b c'");
+            assert_eq!(shell.quote("it's"), r"'it'\''s'");
+            assert_eq!(shell.export("KEY", "v"), "export KEY='v'");
+        }
+    }
+
+    #[test]
+    fn quotes_fish() {
+        assert_eq!(Shell::Fish.quote("a$b c"), "'a$b c'");
+        assert_eq!(Shell::Fish.quote(r"it's\"), r"'it\'s\\'");
+        assert_eq!(Shell::Fish.export("KEY", "v"), "set -gx KEY 'v'");
+    }
+
+    #[test]
+    fn quotes_powershell() {
+        assert_eq!(Shell::Powershell.quote("a$b `c"), "'a$b `c'");
+        assert_eq!(Shell::Powershell.quote("it's"), "'it''s'");
+        assert_eq!(
+            Shell::Powershell.quote("it\u{2019}s"),
+            "'it\u{2019}\u{2019}s'"
+        );
+        assert_eq!(Shell::Powershell.export("KEY", "v"), "$Env:KEY = 'v'");
+    }
+
+    #[test]
+    fn formats_env() {
+        let profile = ShortTermProfile {
+            access_key: "access".to_owned(),
+            secret_key: "secret".to_owned(),
+            session_token: "token".to_owned(),
+            expiration: "2023-04-05T21:57:52Z".parse().unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(
+            env(&profile, Shell::Bash),
+            [
+                "export AWS_ACCESS_KEY_ID='access'",
+                "export AWS_SECRET_ACCESS_KEY='secret'",
+                "export AWS_SESSION_TOKEN='token'",
+                "export AWS_CREDENTIAL_EXPIRATION='2023-04-05T21:57:52Z'",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            env_profile("dev-short-term", Shell::Fish),
+            "set -gx AWS_PROFILE 'dev-short-term'"
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use std::{
+    fmt::{self, Display},
+    fs::{DirBuilder, OpenOptions},
+    path::{Path, PathBuf},
+};
+
+use crate::backup::Backups;
+
+/// What to do when the credentials file or its directory can be accessed by
+/// other users
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum PermissionPolicy {
+    #[default]
+    Warn,
+    Refuse,
+    Ignore,
+}
+
+/// A file or directory that other users can access
+#[derive(Debug, PartialEq)]
+pub struct Issue {
+    pub path: PathBuf,
+    pub is_dir: bool,
+    pub mode: u32,
+    /// The mode without the permissions for other users
+    pub fixed_mode: u32,
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let access = match self.is_dir {
+            true => "writable",
+            false => "accessible",
+        };
+        write!(
+            f,
+            "{:?} is {} by other users (mode {:o})",
+            self.path, access, self.mode
+        )
+    }
+}
+
+impl Issue {
+    #[cfg(unix)]
+    pub fn fix(&self) -> std::io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        std::fs::set_permissions(&self.path, std::fs::Permissions::from_mode(self.fixed_mode))
+    }
+
+    #[cfg(not(unix))]
+    pub fn fix(&self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Files must only be accessible by the owner. Directories may be readable
+/// by others, but not writable, or the files in them could be replaced.
+#[cfg(unix)]
+fn find_issue(path: &Path) -> Option<Issue> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let metadata = std::fs::metadata(path).ok()?;
+    let mode = metadata.permissions().mode() & 0o7777;
+    let forbidden = match metadata.is_dir() {
+        true => 0o022,
+        false => 0o077,
+    };
+    (mode & forbidden != 0).then(|| Issue {
+        path: path.to_path_buf(),
+        is_dir: metadata.is_dir(),
+        mode,
+        fixed_mode: mode & !forbidden,
+    })
+}
+
+#[cfg(not(unix))]
+fn find_issue(_path: &Path) -> Option<Issue> {
+    None
+}
+
+/// Issues with the credentials file and the directory it is in. A symlinked
+/// file is checked where it points to.
+pub fn find_issues(credentials_path: &Path) -> Vec<Issue> {
+    let path =
+        std::fs::canonicalize(credentials_path).unwrap_or_else(|_| credentials_path.to_path_buf());
+    let dir = path.parent().map(Path::to_path_buf);
+    [Some(path), dir]
+        .into_iter()
+        .flatten()
+        .filter_map(|path| find_issue(&path))
+        .collect()
+}
+
+/// Like `find_issues`, but also checks the backups
+pub fn find_all_issues(credentials_path: &Path) -> Vec<Issue> {
+    let path =
+        std::fs::canonicalize(credentials_path).unwrap_or_else(|_| credentials_path.to_path_buf());
+    let backups = Backups::for_file(&path);
+    let mut issues = find_issues(credentials_path);
+    issues.extend(find_issue(backups.dir()));
+    issues.extend(
+        backups
+            .list()
+            .into_iter()
+            .filter_map(|backup| find_issue(&backup.path)),
+    );
+    issues
+}
+
+/// Checks the credentials file when it is loaded
+pub fn check(credentials_path: &Path, policy: PermissionPolicy) -> anyhow::Result<()> {
+    if policy == PermissionPolicy::Ignore {
+        return Ok(());
+    }
+    let issues = find_issues(credentials_path);
+    if issues.is_empty() {
+        return Ok(());
+    }
+    if policy == PermissionPolicy::Refuse {
+        let issues = issues.iter().map(|i| i.to_string()).collect::<Vec<_>>();
+        anyhow::bail!(
+            "{}. Run `mfaws doctor --fix` to fix the permissions",
+            issues.join(", ")
+        );
+    }
+    for issue in issues {
+        warn!("{}. Run `mfaws doctor --fix` to fix it", issue);
+    }
+    Ok(())
+}
+
+/// Options to create a file only the owner can access
+pub fn private_file_options() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+}
+
+/// Creates a directory and its parents, only accessible by the owner
+pub fn create_private_dir(path: &Path) -> std::io::Result<()> {
+    let mut dir_builder = DirBuilder::new();
+    dir_builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut dir_builder, 0o700);
+    dir_builder.create(path)
+}
+
+#[cfg(all(test, unix))]
+mod test_permissions {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    fn set_mode(path: &Path, mode: u32) {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    fn setup(file_mode: u32, dir_mode: u32) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("credentials");
+        std::fs::write(&path, "[dev]").unwrap();
+        set_mode(&path, file_mode);
+        set_mode(dir.path(), dir_mode);
+        (dir, path)
+    }
+
+    #[test]
+    fn finds_and_fixes_issues() {
+        let (dir, path) = setup(0o644, 0o777);
+        let issues = find_issues(&path);
+        assert_eq!(
+            issues,
+            vec![
+                Issue {
+                    path: std::fs::canonicalize(&path).unwrap(),
+                    is_dir: false,
+                    mode: 0o644,
+                    fixed_mode: 0o600,
+                },
+                Issue {
+                    path: std::fs::canonicalize(dir.path()).unwrap(),
+                    is_dir: true,
+                    mode: 0o777,
+                    fixed_mode: 0o755,
+                }
+            ]
+        );
+        for issue in issues {
+            issue.fix().unwrap();
+        }
+        assert!(find_issues(&path).is_empty());
+    }
+
+    #[test]
+    fn readable_directory_is_fine() {
+        let (_dir, path) = setup(0o600, 0o755);
+        assert!(find_issues(&path).is_empty());
+    }
+
+    #[test]
+    fn applies_policy() {
+        let (_dir, path) = setup(0o640, 0o700);
+        assert!(check(&path, PermissionPolicy::Warn).is_ok());
+        assert!(check(&path, PermissionPolicy::Ignore).is_ok());
+        let err = check(&path, PermissionPolicy::Refuse).unwrap_err();
+        assert!(err.to_string().contains("mode 640"));
+    }
+
+    #[test]
+    fn checks_backups() {
+        let (_dir, path) = setup(0o600, 0o700);
+        let backups = Backups::for_file(&path);
+        backups.create("[dev]", 1).unwrap();
+        assert!(find_all_issues(&path).is_empty());
+        set_mode(&backups.list()[0].path, 0o644);
+        assert_eq!(find_all_issues(&path).len(), 1);
+    }
+}
@@ -3,7 +3,13 @@ use aws_sdk_sts::{
     config as StsConfig, primitives::DateTime as AWSDateTime, types as StsTypes,
     Client as STSClient,
 };
-use std::{borrow::Cow, ops::Deref, str::FromStr, time::SystemTime};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    ops::Deref,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
 
 #[derive(Debug, Default)]
 pub struct LongTermProfile<'a> {
@@ -11,6 +17,7 @@ pub struct LongTermProfile<'a> {
     pub access_key: Cow<'a, str>,
     pub secret_key: Cow<'a, str>,
     pub mfa_device: Cow<'a, str>,
+    pub totp_secret: Option<Cow<'a, str>>,
 }
 #[derive(Debug, Default)]
 pub struct ShortTermProfile<'a> {
@@ -20,19 +27,78 @@ pub struct ShortTermProfile<'a> {
     pub expiration: DateTime,
     pub assumed_role_id: Option<String>,
     pub assumed_role_arn: Option<Cow<'a, str>>,
+    pub federated_user_arn: Option<String>,
+    pub federated_user_id: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct DateTime(pub AWSDateTime);
 
+/// How a short-term profile was created. It is written to the credentials
+/// file along with the credentials so that the profile can be renewed.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ProfileSource {
+    pub kind: ProfileKind,
+    /// The profile the credentials were requested for
+    pub profile: String,
+    /// The profile with the long-term credentials, if it differs from
+    /// `profile`, e.g. the end of the role chain of a role profile
+    pub long_term_profile: Option<String>,
+    pub role_session_name: Option<String>,
+    pub duration: Option<i32>,
+    /// The short-term profile whose session token signed the request
+    pub parent: Option<String>,
+    /// The fingerprint of the session policies that scoped down the session
+    pub policy_hash: Option<String>,
+    pub tags: BTreeMap<String, String>,
+    pub transitive_tag_keys: Vec<String>,
+    pub source_identity: Option<String>,
+    pub external_id: Option<String>,
+    /// The MFA device and OTP command from flags or the AWS config file. A
+    /// device in the credentials file is read from there again on renewal.
+    pub mfa_device: Option<String>,
+    pub otp_command: Option<String>,
+    pub sts_region: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ProfileKind {
+    #[default]
+    SessionToken,
+    AssumeRole,
+    FederationToken,
+    WebIdentity,
+    Saml,
+}
+
 pub trait Profile {
     const ACCESS_KEY: &'static str = "aws_access_key_id";
     const SECRET_KEY: &'static str = "aws_secret_access_key";
     const MFA_DEVICE: &'static str = "aws_mfa_device";
+    const MFA_TOTP_SECRET: &'static str = "aws_mfa_totp_secret";
     const SESSION_TOKEN: &'static str = "aws_session_token";
     const ASSUMED_ROLE_ARN: &'static str = "assumed_role_arn";
     const ASSUMED_ROLE_ID: &'static str = "assumed_role_id";
+    const FEDERATED_USER_ARN: &'static str = "federated_user_arn";
+    const FEDERATED_USER_ID: &'static str = "federated_user_id";
     const EXPIRATION: &'static str = "expiration";
+    // Prefixed so that they are not mistaken for AWS settings such as
+    // "source_profile"
+    const SOURCE_KIND: &'static str = "mfaws_kind";
+    const SOURCE_PROFILE: &'static str = "mfaws_source_profile";
+    const SOURCE_LONG_TERM_PROFILE: &'static str = "mfaws_long_term_profile";
+    const SOURCE_ROLE_SESSION_NAME: &'static str = "mfaws_role_session_name";
+    const SOURCE_DURATION: &'static str = "mfaws_duration";
+    const SOURCE_PARENT: &'static str = "mfaws_parent_profile";
+    const SOURCE_POLICY_HASH: &'static str = "mfaws_policy_hash";
+    /// The session tags as a JSON object, tag keys may contain `=`
+    const SOURCE_TAGS: &'static str = "mfaws_tags";
+    const SOURCE_TRANSITIVE_TAG_KEYS: &'static str = "mfaws_transitive_tag_keys";
+    const SOURCE_SOURCE_IDENTITY: &'static str = "mfaws_source_identity";
+    const SOURCE_EXTERNAL_ID: &'static str = "mfaws_external_id";
+    const SOURCE_MFA_DEVICE: &'static str = "mfaws_mfa_device";
+    const SOURCE_OTP_COMMAND: &'static str = "mfaws_otp_command";
+    const SOURCE_STS_REGION: &'static str = "mfaws_sts_region";
 }
 
 impl<'a> Profile for LongTermProfile<'a> {}
@@ -62,13 +128,68 @@ impl FromStr for DateTime {
     }
 }
 
+impl ProfileKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProfileKind::SessionToken => "session-token",
+            ProfileKind::AssumeRole => "assume-role",
+            ProfileKind::FederationToken => "federation-token",
+            ProfileKind::WebIdentity => "web-identity",
+            ProfileKind::Saml => "saml",
+        }
+    }
+
+    /// Whether the daemon can renew profiles of this kind. Federation tokens
+    /// are not renewed because their session policy is not recorded, web
+    /// identities and SAML roles because their token or assertion is not.
+    pub fn is_renewable(&self) -> bool {
+        matches!(self, ProfileKind::SessionToken | ProfileKind::AssumeRole)
+    }
+}
+
+impl FromStr for ProfileKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "session-token" => Ok(ProfileKind::SessionToken),
+            "assume-role" => Ok(ProfileKind::AssumeRole),
+            "federation-token" => Ok(ProfileKind::FederationToken),
+            "web-identity" => Ok(ProfileKind::WebIdentity),
+            "saml" => Ok(ProfileKind::Saml),
+            _ => anyhow::bail!("Unknown profile kind \"{}\"", s),
+        }
+    }
+}
+
 impl TryFrom<DateTime> for SystemTime {
     type Error = aws_smithy_types::date_time::ConversionError;
     fn try_from(value: DateTime) -> Result<Self, Self::Error> {
-        Ok(value.0.try_into()?)
+        value.0.try_into()
     }
 }
 
+fn create_client(credentials: AWSCredentials, region: String) -> STSClient {
+    let conf = StsConfig::Builder::new()
+        .behavior_version(StsConfig::BehaviorVersion::v2024_03_28())
+        .credentials_provider(credentials)
+        .region(Some(StsConfig::Region::new(region)))
+        .build();
+
+    STSClient::from_conf(conf)
+}
+
+/// A client without credentials, for the STS operations that are not signed
+/// such as `AssumeRoleWithWebIdentity` and `AssumeRoleWithSAML`
+pub fn create_unsigned_client(region: String) -> STSClient {
+    let conf = StsConfig::Builder::new()
+        .behavior_version(StsConfig::BehaviorVersion::v2024_03_28())
+        .region(Some(StsConfig::Region::new(region)))
+        .build();
+
+    STSClient::from_conf(conf)
+}
+
 impl<'a> LongTermProfile<'a> {
     pub async fn create_client(&self, region: String) -> STSClient {
         let credentials = AWSCredentials::new(
@@ -78,17 +199,62 @@ impl<'a> LongTermProfile<'a> {
             None,
             "_",
         );
-        let conf = StsConfig::Builder::new()
-            .behavior_version(StsConfig::BehaviorVersion::v2024_03_28())
-            .credentials_provider(credentials)
-            .region(Some(StsConfig::Region::new(region)))
-            .build();
-
-        STSClient::from_conf(conf)
+        create_client(credentials, region)
     }
 }
 
 impl<'a> ShortTermProfile<'a> {
+    pub async fn create_client(&self, region: String) -> STSClient {
+        let credentials = AWSCredentials::new(
+            self.access_key.clone(),
+            self.secret_key.clone(),
+            Some(self.session_token.clone()),
+            None,
+            "_",
+        );
+        create_client(credentials, region)
+    }
+
+    /// The environment variables the AWS SDKs read credentials from
+    pub fn env_vars(&self) -> [(&'static str, String); 4] {
+        [
+            ("AWS_ACCESS_KEY_ID", self.access_key.clone()),
+            ("AWS_SECRET_ACCESS_KEY", self.secret_key.clone()),
+            ("AWS_SESSION_TOKEN", self.session_token.clone()),
+            ("AWS_CREDENTIAL_EXPIRATION", self.format_expiration()),
+        ]
+    }
+
+    /// Whether the credentials expire within the given duration or have
+    /// already expired
+    pub fn expires_within(&self, duration: Duration) -> bool {
+        SystemTime::try_from(self.expiration.clone())
+            .map(|expiration| expiration <= SystemTime::now() + duration)
+            .unwrap_or(true)
+    }
+
+    /// Whether the credentials expired more than the given duration ago
+    pub fn expired_before(&self, duration: Duration) -> bool {
+        SystemTime::try_from(self.expiration.clone())
+            .map(|expiration| expiration + duration < SystemTime::now())
+            .unwrap_or(true)
+    }
+
+    pub fn into_owned(self) -> ShortTermProfile<'static> {
+        ShortTermProfile {
+            access_key: self.access_key,
+            secret_key: self.secret_key,
+            session_token: self.session_token,
+            expiration: self.expiration,
+            assumed_role_id: self.assumed_role_id,
+            assumed_role_arn: self
+                .assumed_role_arn
+                .map(|arn| Cow::Owned(arn.into_owned())),
+            federated_user_arn: self.federated_user_arn,
+            federated_user_id: self.federated_user_id,
+        }
+    }
+
     pub fn format_expiration(&self) -> String {
         self.expiration
             .fmt(aws_smithy_types::date_time::Format::DateTime)
@@ -108,6 +274,8 @@ impl<'a> TryFrom<Option<StsTypes::Credentials>> for ShortTermProfile<'a> {
             expiration: DateTime(creds.expiration),
             assumed_role_arn: None,
             assumed_role_id: None,
+            federated_user_arn: None,
+            federated_user_id: None,
         })
     }
 }
@@ -0,0 +1,519 @@
+use std::time::{Duration, SystemTime};
+
+use aws_sdk_sts::primitives::DateTime as AWSDateTime;
+use aws_smithy_types::date_time::Format;
+use rand::{distr::Alphanumeric, Rng};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::profile::ShortTermProfile;
+
+/// Where the server gets its credentials from.
+pub trait CredentialsSource {
+    /// Returns short-term credentials. If `force` is set, cached credentials
+    /// must not be reused.
+    async fn fetch(&mut self, force: bool) -> anyhow::Result<ShortTermProfile<'static>>;
+    /// The name of the short-term profile the credentials belong to
+    fn profile_name(&self) -> String;
+}
+
+/// Which credential provider of the AWS SDKs the server is compatible with.
+pub enum ServerMode {
+    /// Container credentials (`AWS_CONTAINER_CREDENTIALS_FULL_URI`), clients
+    /// must send the token in the Authorization header
+    Container { auth_token: String },
+    /// EC2 instance metadata service (IMDSv2), clients must get a session
+    /// token first
+    Imds,
+}
+
+/// A minimal HTTP server that serves short-term credentials to the AWS SDKs.
+/// Requests are handled one at a time so that an MFA prompt in the terminal
+/// is never interleaved with another one.
+pub struct CredentialsServer<S> {
+    source: S,
+    mode: ServerMode,
+    refresh_margin: Duration,
+    /// The current credentials and when they were fetched
+    credentials: Option<(ShortTermProfile<'static>, SystemTime)>,
+    /// IMDSv2 session tokens and when they expire
+    imds_tokens: Vec<(String, SystemTime)>,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+pub fn random_token(length: usize) -> String {
+    rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+impl<S> CredentialsServer<S>
+where
+    S: CredentialsSource,
+{
+    const READ_TIMEOUT: Duration = Duration::from_secs(5);
+    const MAX_HEADERS: usize = 100;
+    const IMDS_TOKEN_LENGTH: usize = 44;
+    const IMDS_MAX_TOKEN_TTL: u64 = 21600;
+    const IMDS_TOKEN_PATH: &'static str = "/latest/api/token";
+    const IMDS_CREDENTIALS_PATH: &'static str = "/latest/meta-data/iam/security-credentials/";
+
+    pub fn new(source: S, mode: ServerMode, refresh_margin: Duration) -> Self {
+        Self {
+            source,
+            mode,
+            refresh_margin,
+            credentials: None,
+            imds_tokens: Vec::new(),
+        }
+    }
+
+    pub async fn run(mut self, listener: TcpListener) -> anyhow::Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            if let Err(err) = self.handle(stream).await {
+                warn!("Failed to handle request: {}", err);
+            }
+        }
+    }
+
+    async fn handle(&mut self, mut stream: TcpStream) -> anyhow::Result<()> {
+        let request = tokio::time::timeout(Self::READ_TIMEOUT, Self::read_request(&mut stream))
+            .await
+            .map_err(|_| anyhow::anyhow!("Timed out reading request"))??;
+        let response = match self.mode {
+            ServerMode::Container { .. } => self.respond_container(&request).await,
+            ServerMode::Imds => self.respond_imds(&request).await,
+        };
+        let message = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            response.status,
+            response.content_type,
+            response.body.len(),
+            response.body
+        );
+        stream.write_all(message.as_bytes()).await?;
+        stream.shutdown().await?;
+        Ok(())
+    }
+
+    async fn read_request(stream: &mut TcpStream) -> anyhow::Result<Request> {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).await?;
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+            anyhow::bail!("Malformed request line");
+        };
+        let mut request = Request {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            headers: Vec::new(),
+        };
+
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).await? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if request.headers.len() >= Self::MAX_HEADERS {
+                anyhow::bail!("Too many headers");
+            }
+            if let Some((key, value)) = line.split_once(':') {
+                request
+                    .headers
+                    .push((key.trim().to_lowercase(), value.trim().to_owned()));
+            }
+        }
+        Ok(request)
+    }
+
+    async fn respond_container(&mut self, request: &Request) -> Response {
+        let ServerMode::Container { ref auth_token } = self.mode else {
+            unreachable!()
+        };
+        if request.header("authorization") != Some(auth_token) {
+            return Response::error("401 Unauthorized", "Invalid authorization token");
+        }
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/") => match self.credentials().await {
+                Ok((credentials, _)) => Response::json(container_credentials(credentials)),
+                Err(err) => Response::internal_error(err),
+            },
+            _ => Response::error("404 Not Found", "Not found"),
+        }
+    }
+
+    async fn respond_imds(&mut self, request: &Request) -> Response {
+        let now = SystemTime::now();
+        self.imds_tokens.retain(|(_, expiration)| *expiration > now);
+
+        if request.path == Self::IMDS_TOKEN_PATH {
+            return match request.method.as_str() {
+                "PUT" => self.create_imds_token(request, now),
+                _ => Response::error("405 Method Not Allowed", "Method not allowed"),
+            };
+        }
+        let authorized = request
+            .header("x-aws-ec2-metadata-token")
+            .is_some_and(|token| self.imds_tokens.iter().any(|(t, _)| t == token));
+        if !authorized {
+            return Response::error("401 Unauthorized", "Missing or invalid session token");
+        }
+
+        let profile_name = self.source.profile_name();
+        match (
+            request.method.as_str(),
+            request.path.strip_prefix(Self::IMDS_CREDENTIALS_PATH),
+        ) {
+            ("GET", Some("")) => Response::text(profile_name),
+            ("GET", Some(name)) if name.trim_end_matches('/') == profile_name => {
+                match self.credentials().await {
+                    Ok((credentials, last_updated)) => {
+                        Response::json(imds_credentials(credentials, *last_updated))
+                    }
+                    Err(err) => Response::internal_error(err),
+                }
+            }
+            _ => Response::error("404 Not Found", "Not found"),
+        }
+    }
+
+    fn create_imds_token(&mut self, request: &Request, now: SystemTime) -> Response {
+        // Like IMDS, refuse tokens for requests that went through a proxy
+        if request.header("x-forwarded-for").is_some() {
+            return Response::error("403 Forbidden", "Forwarded requests are not allowed");
+        }
+        let ttl = request
+            .header("x-aws-ec2-metadata-token-ttl-seconds")
+            .and_then(|ttl| ttl.parse::<u64>().ok())
+            .filter(|ttl| (1..=Self::IMDS_MAX_TOKEN_TTL).contains(ttl));
+        let Some(ttl) = ttl else {
+            return Response::error("400 Bad Request", "Missing or invalid token TTL");
+        };
+        let token = random_token(Self::IMDS_TOKEN_LENGTH);
+        self.imds_tokens
+            .push((token.clone(), now + Duration::from_secs(ttl)));
+        Response::text(token)
+    }
+
+    /// Returns the current credentials and when they were fetched, refreshing
+    /// them if they are about to expire.
+    async fn credentials(&mut self) -> anyhow::Result<&(ShortTermProfile<'static>, SystemTime)> {
+        let expiring = |creds: &ShortTermProfile| creds.expires_within(self.refresh_margin);
+        if self
+            .credentials
+            .as_ref()
+            .is_none_or(|(creds, _)| expiring(creds))
+        {
+            let mut credentials = self.source.fetch(false).await?;
+            // Cached credentials from the credentials file might be about
+            // to expire as well
+            if expiring(&credentials) {
+                credentials = self.source.fetch(true).await?;
+            }
+            info!(
+                "Serving credentials that expire at {}",
+                credentials.format_expiration()
+            );
+            self.credentials = Some((credentials, SystemTime::now()));
+        }
+        self.credentials
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No credentials available"))
+    }
+}
+
+impl Request {
+    fn header(&self, key: &str) -> Option<&String> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value)
+    }
+}
+
+impl Response {
+    fn json(body: String) -> Self {
+        Self {
+            status: "200 OK",
+            content_type: "application/json",
+            body,
+        }
+    }
+
+    fn text(body: String) -> Self {
+        Self {
+            status: "200 OK",
+            content_type: "text/plain",
+            body,
+        }
+    }
+
+    fn error(status: &'static str, message: &str) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: serde_json::json!({ "Code": status, "Message": message }).to_string(),
+        }
+    }
+
+    fn internal_error(err: anyhow::Error) -> Self {
+        error!("{}", err);
+        Self::error("500 Internal Server Error", &err.to_string())
+    }
+}
+
+/// Formats the credentials as expected by the container credentials provider.
+fn container_credentials(profile: &ShortTermProfile) -> String {
+    let mut body = serde_json::json!({
+        "AccessKeyId": profile.access_key,
+        "SecretAccessKey": profile.secret_key,
+        "Token": profile.session_token,
+        "Expiration": profile.format_expiration(),
+    });
+    if let Some(ref arn) = profile.assumed_role_arn {
+        body["RoleArn"] = serde_json::json!(arn);
+    }
+    body.to_string()
+}
+
+/// Formats the credentials as expected by the instance metadata provider.
+fn imds_credentials(profile: &ShortTermProfile, last_updated: SystemTime) -> String {
+    serde_json::json!({
+        "Code": "Success",
+        "LastUpdated": AWSDateTime::from(last_updated)
+            .fmt(Format::DateTime)
+            .unwrap_or_default(),
+        "Type": "AWS-HMAC",
+        "AccessKeyId": profile.access_key,
+        "SecretAccessKey": profile.secret_key,
+        "Token": profile.session_token,
+        "Expiration": profile.format_expiration(),
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod test_server {
+    use std::borrow::Cow;
+
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    const TOKEN: &str = "secret-token";
+    const PROFILE: &str = "dev-short-term";
+
+    struct StaticSource {
+        expiration: &'static str,
+        fetched: usize,
+    }
+
+    impl CredentialsSource for StaticSource {
+        async fn fetch(&mut self, _force: bool) -> anyhow::Result<ShortTermProfile<'static>> {
+            self.fetched += 1;
+            Ok(ShortTermProfile {
+                access_key: format!("access-{}", self.fetched),
+                secret_key: "secret".to_owned(),
+                session_token: "token".to_owned(),
+                expiration: self.expiration.parse().unwrap(),
+                assumed_role_arn: Some(Cow::Borrowed("arn:aws:iam::123456789012:role/admin")),
+                ..Default::default()
+            })
+        }
+
+        fn profile_name(&self) -> String {
+            PROFILE.to_owned()
+        }
+    }
+
+    async fn request(
+        addr: std::net::SocketAddr,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+    ) -> (String, String) {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let mut request = format!("{} {} HTTP/1.1\r\nHost: localhost\r\n", method, path);
+        for (key, value) in headers {
+            request += &format!("{}: {}\r\n", key, value);
+        }
+        request += "\r\n";
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.lines().next().unwrap().to_owned();
+        (status, body.to_owned())
+    }
+
+    async fn get(addr: std::net::SocketAddr, path: &str, token: &str) -> (String, String) {
+        request(addr, "GET", path, &[("Authorization", token)]).await
+    }
+
+    /// Runs the server until the client is done
+    async fn with_server<F, Fut>(source: StaticSource, mode: ServerMode, client: F)
+    where
+        F: FnOnce(std::net::SocketAddr) -> Fut,
+        Fut: std::future::Future<Output = ()>,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = CredentialsServer::new(source, mode, Duration::from_secs(300));
+        tokio::select! {
+            res = server.run(listener) => panic!("Server stopped: {:?}", res),
+            _ = client(addr) => {}
+        }
+    }
+
+    fn source(expiration: &'static str) -> StaticSource {
+        StaticSource {
+            expiration,
+            fetched: 0,
+        }
+    }
+
+    fn container() -> ServerMode {
+        ServerMode::Container {
+            auth_token: TOKEN.to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_container_credentials() {
+        with_server(
+            source("2099-01-01T00:00:00Z"),
+            container(),
+            |addr| async move {
+                let (status, body) = get(addr, "/", TOKEN).await;
+                assert_eq!(status, "HTTP/1.1 200 OK");
+                let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+                assert_eq!(
+                    json,
+                    serde_json::json!({
+                        "AccessKeyId": "access-1",
+                        "SecretAccessKey": "secret",
+                        "Token": "token",
+                        "Expiration": "2099-01-01T00:00:00Z",
+                        "RoleArn": "arn:aws:iam::123456789012:role/admin",
+                    })
+                );
+                // Valid credentials are not fetched again
+                let (_, body) = get(addr, "/", TOKEN).await;
+                assert!(body.contains("access-1"));
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn refreshes_expiring_credentials() {
+        with_server(
+            source("2000-01-01T00:00:00Z"),
+            container(),
+            |addr| async move {
+                // Expired credentials are fetched again with force
+                let (_, body) = get(addr, "/", TOKEN).await;
+                assert!(body.contains("access-2"));
+                let (_, body) = get(addr, "/", TOKEN).await;
+                assert!(body.contains("access-4"));
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_requests() {
+        with_server(
+            source("2099-01-01T00:00:00Z"),
+            container(),
+            |addr| async move {
+                let (status, _) = get(addr, "/", "wrong-token").await;
+                assert_eq!(status, "HTTP/1.1 401 Unauthorized");
+                let (status, _) = get(addr, "/other", TOKEN).await;
+                assert_eq!(status, "HTTP/1.1 404 Not Found");
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn serves_imds_credentials() {
+        with_server(
+            source("2099-01-01T00:00:00Z"),
+            ServerMode::Imds,
+            |addr| async move {
+                let ttl = [("X-aws-ec2-metadata-token-ttl-seconds", "21600")];
+                let (status, token) = request(addr, "PUT", "/latest/api/token", &ttl).await;
+                assert_eq!(status, "HTTP/1.1 200 OK");
+
+                let headers = [("X-aws-ec2-metadata-token", token.as_str())];
+                let path = "/latest/meta-data/iam/security-credentials/";
+                let (_, role) = request(addr, "GET", path, &headers).await;
+                assert_eq!(role, PROFILE);
+
+                let path = format!("{}{}", path, role);
+                let (status, body) = request(addr, "GET", &path, &headers).await;
+                assert_eq!(status, "HTTP/1.1 200 OK");
+                let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+                assert_eq!(json["Code"], "Success");
+                assert_eq!(json["Type"], "AWS-HMAC");
+                assert_eq!(json["AccessKeyId"], "access-1");
+                assert_eq!(json["SecretAccessKey"], "secret");
+                assert_eq!(json["Token"], "token");
+                assert_eq!(json["Expiration"], "2099-01-01T00:00:00Z");
+                assert!(json["LastUpdated"].is_string());
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_imds_requests() {
+        with_server(
+            source("2099-01-01T00:00:00Z"),
+            ServerMode::Imds,
+            |addr| async move {
+                let path = "/latest/meta-data/iam/security-credentials/";
+                let (status, _) = request(addr, "GET", path, &[]).await;
+                assert_eq!(status, "HTTP/1.1 401 Unauthorized");
+                let headers = [("X-aws-ec2-metadata-token", "invalid")];
+                let (status, _) = request(addr, "GET", path, &headers).await;
+                assert_eq!(status, "HTTP/1.1 401 Unauthorized");
+
+                let (status, _) = request(addr, "PUT", "/latest/api/token", &[]).await;
+                assert_eq!(status, "HTTP/1.1 400 Bad Request");
+                let ttl = [
+                    ("X-aws-ec2-metadata-token-ttl-seconds", "60"),
+                    ("X-Forwarded-For", "10.0.0.1"),
+                ];
+                let (status, _) = request(addr, "PUT", "/latest/api/token", &ttl).await;
+                assert_eq!(status, "HTTP/1.1 403 Forbidden");
+
+                let ttl = [("X-aws-ec2-metadata-token-ttl-seconds", "60")];
+                let (_, token) = request(addr, "PUT", "/latest/api/token", &ttl).await;
+                let headers = [("X-aws-ec2-metadata-token", token.as_str())];
+                let path = format!("{}other-role", path);
+                let (status, _) = request(addr, "GET", &path, &headers).await;
+                assert_eq!(status, "HTTP/1.1 404 Not Found");
+            },
+        )
+        .await;
+    }
+}
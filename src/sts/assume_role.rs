@@ -1,104 +1,702 @@
 use std::borrow::Cow;
 
+use aws_sdk_sts::{operation::assume_role::AssumeRoleOutput, Client as STSClient};
+
 use crate::{
-    profile::{LongTermProfile, ShortTermProfile},
-    sts::{config::CommonStsConfig, extract_sts_err, ShortTermCredentials},
+    aws_config::AwsConfigHandler,
+    mfaws_config::MfawsConfig,
+    profile::{LongTermProfile, ProfileKind, ProfileSource, ShortTermProfile},
+    sts::{
+        config::CommonStsConfig,
+        extract_sts_err,
+        policy::{policy_descriptors, policy_hash, read_policy, validate_policy_arns},
+        require_lt_profile,
+        session_options::SessionOptions,
+        session_token::session_profile_name,
+        ShortTermCredentials,
+    },
 };
 
 #[derive(clap::Args, Debug, Default)]
 pub struct AssumeRole {
+    #[arg(
+        value_name = "ALIAS",
+        help = "An alias from the mfaws config file that sets the role and its settings"
+    )]
+    pub alias: Option<String>,
     #[arg(
         long = "role-arn",
         env = "AWS_ROLE_ARN",
-        help = "The ARN of the AWS IAM Role you want to assume"
+        help = "The ARN of the AWS IAM Role you want to assume. Read from the profile's \"role_arn\" in the AWS config file if omitted"
     )]
-    pub role_arn: String,
+    pub role_arn: Option<String>,
     #[arg(
         long = "role-session-name",
-        default_value = "mfa-user",
         env = "AWS_ROLE_SESSION_NAME",
-        help = "Custom friendly session name when assuming a role"
+        help = "Custom friendly session name when assuming a role [default: mfa-user]"
     )]
-    pub role_name: String,
+    pub role_name: Option<String>,
+    #[arg(
+        long = "policy",
+        value_name = "POLICY",
+        help = "An inline session policy to scope down the role: a file with the policy in JSON, the JSON itself or - to read it from stdin"
+    )]
+    pub policy_path: Option<String>,
+    #[arg(
+        long = "policy-arn",
+        help = "The ARN of a managed policy to scope down the role. Can be given multiple times"
+    )]
+    pub policy_arns: Vec<String>,
+    #[clap(flatten)]
+    pub session: SessionOptions,
     #[clap(flatten)]
     pub config: CommonStsConfig,
+    /// The inline session policy, read from `policy_path` by `resolve`
+    #[arg(skip)]
+    pub policy: Option<String>,
+    /// Roles that are assumed before `role_arn`, resolved from the
+    /// `source_profile` hops in the AWS config file
+    #[arg(skip)]
+    pub chain: Vec<RoleHop>,
+    /// The profile the role was requested for, before the role chain is
+    /// resolved
+    #[arg(skip)]
+    pub requested_profile: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoleHop {
+    pub role_arn: String,
+    pub role_name: String,
+    pub external_id: Option<String>,
+}
+
+/// The name of the short-term profile for a role, made of the profile, the
+/// account and path of the role ARN and the session name
+pub fn role_profile_name(
+    profile_name: &str,
+    role_arn: &str,
+    role_name: &str,
+    short_term_suffix: &str,
+) -> String {
+    let arn = role_arn
+        .split([':', '/'])
+        .skip(4)
+        .collect::<Vec<&str>>()
+        .join("-");
+    format!(
+        "{}_{}-{}_{}",
+        profile_name, arn, role_name, short_term_suffix
+    )
+}
+
+impl AssumeRole {
+    const DEFAULT_SESSION_NAME: &'static str = "mfa-user";
+    /// AWS limits chained role sessions to one hour
+    const MAX_CHAINED_DURATION: i32 = 3600;
+
+    pub fn init(&mut self, aws_config: &AwsConfigHandler) -> anyhow::Result<()> {
+        self.resolve(aws_config)?;
+        if self.role_arn.is_none() {
+            anyhow::bail!(
+                "No role ARN for profile \"{}\", use --role-arn or set \"role_arn\" in the AWS config file",
+                self.config.profile_name
+            );
+        }
+        Ok(())
+    }
+
+    /// Merges the settings of the alias, if any. Must be called before
+    /// `resolve` so that the alias takes precedence over the AWS config file.
+    pub fn apply_alias(&mut self, mfaws_config: &MfawsConfig) -> anyhow::Result<()> {
+        let Some(ref name) = self.alias else {
+            return Ok(());
+        };
+        let alias = mfaws_config.get_alias(name)?;
+        if self.role_arn.is_none() {
+            self.role_arn = alias.role_arn.clone();
+        }
+        if self.role_name.is_none() {
+            self.role_name = alias.role_session_name.clone();
+        }
+        if self.policy_path.is_none() {
+            self.policy_path = alias.policy.clone();
+        }
+        if self.policy_arns.is_empty() {
+            self.policy_arns = alias.policy_arns.clone();
+        }
+        self.session.merge_alias(alias);
+        self.config.merge_alias(alias);
+        Ok(())
+    }
+
+    /// Merges the role settings from the AWS config file. If the profile
+    /// defines a role with a `source_profile`, the source profile hops are
+    /// followed until a profile with long-term credentials is found. Every
+    /// role in between becomes a hop in the role chain. If the role ARN is
+    /// already set by the flag or an alias, the role of the profile itself
+    /// is a hop as well.
+    pub fn resolve(&mut self, aws_config: &AwsConfigHandler) -> anyhow::Result<()> {
+        self.requested_profile
+            .get_or_insert_with(|| self.config.profile_name.clone());
+        let chain = aws_config.get_role_chain(&self.config.profile_name)?;
+        let target_from_profile = self.role_arn.is_none() && !chain.roles.is_empty();
+        if target_from_profile {
+            let target = &chain.roles[0];
+            self.role_arn = target.role_arn.clone();
+            if self.role_name.is_none() {
+                self.role_name = target.role_session_name.clone();
+            }
+            if self.session.external_id.is_none() {
+                self.session.external_id = target.external_id.clone();
+            }
+        } else if let Some(profile) = aws_config
+            .get_profile(&self.config.profile_name)?
+            .filter(|_| chain.roles.is_empty())
+        {
+            if self.role_name.is_none() {
+                self.role_name = profile.role_session_name;
+            }
+        }
+        self.role_name
+            .get_or_insert_with(|| Self::DEFAULT_SESSION_NAME.to_owned());
+
+        for profile in &chain.roles {
+            self.config.merge_config_profile(profile);
+        }
+        self.chain = chain
+            .roles
+            .iter()
+            .skip(usize::from(target_from_profile))
+            .rev()
+            .map(|profile| RoleHop {
+                role_arn: profile.role_arn.clone().unwrap_or_default(),
+                role_name: profile
+                    .role_session_name
+                    .clone()
+                    .unwrap_or_else(|| Self::DEFAULT_SESSION_NAME.to_owned()),
+                external_id: profile.external_id.clone(),
+            })
+            .collect();
+        self.config.profile_name = chain.source_profile;
+        self.config.init(aws_config)?;
+        self.limit_chained_duration();
+        self.session.validate()?;
+        self.load_policy()
+    }
+
+    fn load_policy(&mut self) -> anyhow::Result<()> {
+        if let Some(ref path) = self.policy_path {
+            self.policy = Some(read_policy(path)?);
+        }
+        validate_policy_arns(&self.policy_arns)
+    }
+
+    /// The fingerprint of the session policies, if the role is scoped down
+    pub fn policy_hash(&self) -> Option<String> {
+        policy_hash(self.policy.as_deref(), &self.policy_arns)
+    }
+
+    fn limit_chained_duration(&mut self) {
+        if self.chain.is_empty() {
+            return;
+        }
+        if let Some(duration) = self.config.duration {
+            if duration > Self::MAX_CHAINED_DURATION {
+                warn!(
+                    "Duration of {}s exceeds the limit for chained roles, using {}s",
+                    duration,
+                    Self::MAX_CHAINED_DURATION
+                );
+                self.config.duration = Some(Self::MAX_CHAINED_DURATION);
+            }
+        }
+    }
+
+    /// All roles to assume in order with their session name and external ID,
+    /// ending with the target role
+    pub fn hops(&self) -> impl Iterator<Item = (&str, &str, Option<&str>)> {
+        self.chain
+            .iter()
+            .map(|hop| {
+                (
+                    hop.role_arn.as_str(),
+                    hop.role_name.as_str(),
+                    hop.external_id.as_deref(),
+                )
+            })
+            .chain(std::iter::once((
+                self.role_arn(),
+                self.role_name(),
+                self.session.external_id.as_deref(),
+            )))
+    }
+
+    /// Assumes every role of the chain in order. Only the first hop is signed
+    /// by `client` and uses the MFA device, every subsequent hop is signed
+    /// with the credentials of the previous one. The session policies, tags
+    /// and source identity only apply to the target role.
+    #[cfg(not(feature = "e2e_test"))]
+    async fn assume_hops(
+        &self,
+        config: &CommonStsConfig,
+        mut client: STSClient,
+        mut mfa: Option<(String, String)>,
+    ) -> anyhow::Result<ShortTermProfile<'_>> {
+        let mut output: Option<AssumeRoleOutput> = None;
+
+        let target = self.chain.len();
+        let tags = self.session.sts_tags()?;
+        for (index, (role_arn, role_name, external_id)) in self.hops().enumerate() {
+            let is_target = index == target;
+            if let Some(previous) = output.take() {
+                client = ShortTermProfile::try_from(previous.credentials)?
+                    .create_client(config.sts_region.clone())
+                    .await;
+            }
+            let (serial_number, token_code) = mfa.take().unzip();
+            let hop = client
+                .assume_role()
+                .role_arn(role_arn)
+                .role_session_name(role_name)
+                .set_serial_number(serial_number)
+                .set_token_code(token_code)
+                .set_policy(self.policy.clone().filter(|_| is_target))
+                .set_policy_arns(policy_descriptors(&self.policy_arns).filter(|_| is_target))
+                .set_external_id(external_id.map(|id| id.to_owned()))
+                .set_tags(tags.clone().filter(|_| is_target))
+                .set_transitive_tag_keys(
+                    Some(self.session.transitive_tag_keys.clone())
+                        .filter(|keys| is_target && !keys.is_empty()),
+                )
+                .set_source_identity(self.session.source_identity.clone().filter(|_| is_target))
+                .set_duration_seconds(config.duration.or(Some(Self::DEFAULT_DURATION)))
+                .send()
+                .await
+                .map_err(extract_sts_err)?;
+            output = Some(hop);
+        }
+        let output = output.ok_or_else(|| anyhow::anyhow!("No role to assume"))?;
+
+        let mut short_term_profile = ShortTermProfile::try_from(output.credentials)?;
+
+        // Assumed_role_arn is the user input role_arn, not the actual
+        // role_arn returned by STS
+        short_term_profile.assumed_role_arn = Some(Cow::Borrowed(self.role_arn()));
+        // Assumed_role_id is the actual role_id returned by STS
+        short_term_profile.assumed_role_id = output.assumed_role_user.map(|v| v.assumed_role_id);
+
+        Ok(short_term_profile)
+    }
+
+    pub fn role_arn(&self) -> &str {
+        self.role_arn.as_deref().unwrap_or_default()
+    }
+
+    pub fn role_name(&self) -> &str {
+        self.role_name.as_deref().unwrap_or_default()
+    }
 }
 
 impl ShortTermCredentials for AssumeRole {
     const DEFAULT_DURATION: i32 = 3600;
 
+    /// Scoped-down and tagged sessions get the hashes of their session
+    /// policies and session options appended to the session name, so that
+    /// they are not mistaken for the full role and a differently scoped or
+    /// tagged session does not reuse the cached profile
     fn short_profile_name(&self) -> String {
-        let arn = self
-            .role_arn
-            .split([':', '/'])
-            .skip(4)
-            .collect::<Vec<&str>>()
-            .join("-");
-        self.config.profile_name.clone()
-            + "_"
-            + &arn
-            + "-"
-            + &self.role_name
-            + "_"
-            + &self.config.short_term_suffix
-    }
-
-    fn config<'c>(&'c self) -> &'c CommonStsConfig {
+        let role_name = [self.policy_hash(), self.session.hash()]
+            .into_iter()
+            .flatten()
+            .fold(self.role_name().to_owned(), |name, hash| {
+                format!("{}-{}", name, hash)
+            });
+        role_profile_name(
+            &self.config.profile_name,
+            self.role_arn(),
+            &role_name,
+            &self.config.short_term_suffix,
+        )
+    }
+
+    fn parent_profile_name(&self) -> Option<String> {
+        Some(session_profile_name(
+            &self.config.profile_name,
+            &self.config.short_term_suffix,
+        ))
+    }
+
+    fn profile_source(&self) -> ProfileSource {
+        let profile = self
+            .requested_profile
+            .clone()
+            .unwrap_or_else(|| self.config.profile_name.clone());
+        ProfileSource {
+            kind: ProfileKind::AssumeRole,
+            long_term_profile: Some(self.config.profile_name.clone())
+                .filter(|long_term| *long_term != profile),
+            profile,
+            role_session_name: self.role_name.clone(),
+            duration: self.config.duration,
+            policy_hash: self.policy_hash(),
+            tags: self.session.tag_map(),
+            transitive_tag_keys: self.session.transitive_tag_keys.clone(),
+            source_identity: self.session.source_identity.clone(),
+            external_id: self.session.external_id.clone(),
+            mfa_device: self.config.mfa_device.clone(),
+            otp_command: self.config.otp_command.clone(),
+            sts_region: Some(self.config.sts_region.clone()),
+            ..Default::default()
+        }
+    }
+
+    fn config(&self) -> &CommonStsConfig {
         &self.config
     }
 
+    fn config_mut(&mut self) -> &mut CommonStsConfig {
+        &mut self.config
+    }
+
     fn log_action(&self) {
         info!(
             "Assuming role \"{}\" for \"{}\"",
-            self.role_arn, self.role_name
+            self.role_arn(),
+            self.role_name()
         );
+        if !self.chain.is_empty() {
+            info!(
+                "Chaining through {}",
+                self.chain
+                    .iter()
+                    .map(|hop| format!("\"{}\"", hop.role_arn))
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            );
+        }
     }
 
     #[cfg(not(feature = "e2e_test"))]
-    async fn get_credentials(
+    async fn get_credentials_with_session(
         &self,
         config: &CommonStsConfig,
-        mfa_token: String,
-        lt_profile: &LongTermProfile<'_>,
-    ) -> anyhow::Result<ShortTermProfile> {
-        let output = lt_profile
-            .create_client(config.sts_region.clone())
-            .await
-            .assume_role()
-            .set_role_arn(Some(self.role_arn.clone()))
-            .set_role_session_name(Some(self.role_name.clone()))
-            .set_serial_number(Some(lt_profile.mfa_device.to_string()))
-            .set_token_code(Some(mfa_token))
-            .set_duration_seconds(config.duration.or(Some(Self::DEFAULT_DURATION)))
-            .send()
-            .await
-            .map_err(extract_sts_err)?;
-
-        let mut short_term_profile = ShortTermProfile::try_from(output.credentials)?;
+        parent: &ShortTermProfile<'_>,
+    ) -> anyhow::Result<ShortTermProfile<'_>> {
+        let client = parent.create_client(config.sts_region.clone()).await;
+        self.assume_hops(config, client, None).await
+    }
 
-        // Assumed_role_arn is the user input role_arn, not the actual
-        // role_arn returned by STS
-        short_term_profile.assumed_role_arn = Some(Cow::Borrowed(&self.role_arn));
-        // Assumed_role_id is the actual role_id returned by STS
-        short_term_profile.assumed_role_id = output.assumed_role_user.map(|v| v.assumed_role_id);
+    #[cfg(feature = "e2e_test")]
+    async fn get_credentials_with_session(
+        &self,
+        config: &CommonStsConfig,
+        parent: &ShortTermProfile<'_>,
+    ) -> anyhow::Result<ShortTermProfile<'_>> {
+        Ok(ShortTermProfile {
+            access_key: "sts-access-key".to_owned(),
+            secret_key: "sts-secret-key".to_owned(),
+            session_token: "sts-session-token".to_owned(),
+            assumed_role_id: Some(self.role_arn().to_string()),
+            assumed_role_arn: Some(Cow::Owned("1111/user".to_owned())),
+            ..Default::default()
+        })
+    }
 
-        Ok(short_term_profile)
+    #[cfg(not(feature = "e2e_test"))]
+    async fn get_credentials(
+        &self,
+        config: &CommonStsConfig,
+        mfa_token: Option<String>,
+        lt_profile: Option<&LongTermProfile<'_>>,
+    ) -> anyhow::Result<ShortTermProfile<'_>> {
+        let lt_profile = require_lt_profile(lt_profile)?;
+        let client = lt_profile.create_client(config.sts_region.clone()).await;
+        let mfa = mfa_token.map(|token| (lt_profile.mfa_device.to_string(), token));
+        self.assume_hops(config, client, mfa).await
     }
 
     #[cfg(feature = "e2e_test")]
     async fn get_credentials(
         &self,
         config: &CommonStsConfig,
-        mfa_token: String,
-        lt_profile: &LongTermProfile<'_>,
-    ) -> anyhow::Result<ShortTermProfile> {
+        mfa_token: Option<String>,
+        lt_profile: Option<&LongTermProfile<'_>>,
+    ) -> anyhow::Result<ShortTermProfile<'_>> {
         Ok(ShortTermProfile {
             access_key: "sts-access-key".to_owned(),
             secret_key: "sts-secret-key".to_owned(),
             session_token: "sts-session-token".to_owned(),
-            assumed_role_id: Some(self.role_arn.to_string()),
+            assumed_role_id: Some(self.role_arn().to_string()),
             assumed_role_arn: Some(Cow::Owned("1111/user".to_owned())),
             ..Default::default()
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn init_from_config_profile() {
+        let aws_config = AwsConfigHandler::_new(
+            r#"[profile admin]
+            role_arn = arn:aws:iam::123456789012:role/admin
+            source_profile = dev
+            mfa_serial = arn:aws:iam::123456789012:mfa/user
+            role_session_name = session"#,
+        )
+        .unwrap();
+        let mut cmd = AssumeRole {
+            config: CommonStsConfig {
+                profile_name: "admin".to_owned(),
+                short_term_suffix: "short-term".to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        cmd.init(&aws_config).unwrap();
+
+        assert_eq!(cmd.role_arn(), "arn:aws:iam::123456789012:role/admin");
+        assert_eq!(cmd.role_name(), "session");
+        assert_eq!(cmd.config.profile_name, "dev");
+        assert_eq!(
+            cmd.config.mfa_device.as_deref(),
+            Some("arn:aws:iam::123456789012:mfa/user")
+        );
+        assert_eq!(
+            cmd.short_profile_name(),
+            "dev_123456789012-role-admin-session_short-term"
+        );
+    }
+
+    #[test]
+    fn init_role_chain() {
+        let aws_config = AwsConfigHandler::_new(
+            r#"[profile break-glass]
+            role_arn = arn:aws:iam::333333333333:role/break-glass
+            source_profile = workload
+            duration_seconds = 43200
+            [profile workload]
+            role_arn = arn:aws:iam::222222222222:role/workload
+            source_profile = identity
+            role_session_name = workload-session
+            external_id = workload-id
+            [profile identity]
+            role_arn = arn:aws:iam::111111111111:role/identity
+            source_profile = dev
+            mfa_serial = arn:aws:iam::111111111111:mfa/user"#,
+        )
+        .unwrap();
+        let mut cmd = AssumeRole {
+            config: CommonStsConfig {
+                profile_name: "break-glass".to_owned(),
+                short_term_suffix: "short-term".to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        cmd.init(&aws_config).unwrap();
+
+        assert_eq!(cmd.config.profile_name, "dev");
+        assert_eq!(
+            cmd.config.mfa_device.as_deref(),
+            Some("arn:aws:iam::111111111111:mfa/user")
+        );
+        assert_eq!(cmd.config.duration, Some(AssumeRole::MAX_CHAINED_DURATION));
+        assert_eq!(cmd.profile_source().profile, "break-glass");
+        assert_eq!(
+            cmd.profile_source().long_term_profile.as_deref(),
+            Some("dev")
+        );
+        assert_eq!(cmd.parent_profile_name().unwrap(), "dev-short-term");
+        assert_eq!(
+            cmd.hops().collect::<Vec<_>>(),
+            vec![
+                ("arn:aws:iam::111111111111:role/identity", "mfa-user", None),
+                (
+                    "arn:aws:iam::222222222222:role/workload",
+                    "workload-session",
+                    Some("workload-id")
+                ),
+                (
+                    "arn:aws:iam::333333333333:role/break-glass",
+                    "mfa-user",
+                    None
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn init_from_alias() {
+        let mfaws_config = MfawsConfig::_new(
+            r#"[aliases.prod-admin]
+            role_arn = "arn:aws:iam::123456789012:role/admin"
+            role_session_name = "alias-session"
+            source_profile = "admin"
+            duration = 900"#,
+        )
+        .unwrap();
+        let aws_config = AwsConfigHandler::_new(
+            r#"[profile admin]
+            role_arn = arn:aws:iam::123456789012:role/other
+            source_profile = dev
+            role_session_name = config-session
+            duration_seconds = 1800"#,
+        )
+        .unwrap();
+        let mut cmd = AssumeRole {
+            alias: Some("prod-admin".to_owned()),
+            config: CommonStsConfig {
+                profile_name: "default".to_owned(),
+                short_term_suffix: "short-term".to_owned(),
+                defaulted: vec!["profile_name".to_owned()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        cmd.apply_alias(&mfaws_config).unwrap();
+        cmd.init(&aws_config).unwrap();
+
+        assert_eq!(cmd.role_arn(), "arn:aws:iam::123456789012:role/admin");
+        assert_eq!(cmd.role_name(), "alias-session");
+        assert_eq!(cmd.config.profile_name, "dev");
+        assert_eq!(cmd.config.duration, Some(900));
+        assert_eq!(
+            cmd.hops().map(|(arn, ..)| arn).collect::<Vec<_>>(),
+            vec![
+                "arn:aws:iam::123456789012:role/other",
+                "arn:aws:iam::123456789012:role/admin"
+            ]
+        );
+
+        cmd.alias = Some("missing".to_owned());
+        assert!(cmd.apply_alias(&mfaws_config).is_err());
+    }
+
+    #[test]
+    fn init_from_alias_with_role_source_profile() {
+        let mfaws_config = MfawsConfig::_new(
+            r#"[aliases.prod-admin]
+            role_arn = "arn:aws:iam::333333333333:role/admin"
+            source_profile = "mid""#,
+        )
+        .unwrap();
+        let aws_config = AwsConfigHandler::_new(
+            r#"[profile mid]
+            role_arn = arn:aws:iam::222222222222:role/mid
+            source_profile = base
+            role_session_name = mid-session
+            external_id = mid-id"#,
+        )
+        .unwrap();
+        let mut cmd = AssumeRole {
+            alias: Some("prod-admin".to_owned()),
+            config: CommonStsConfig {
+                profile_name: "default".to_owned(),
+                short_term_suffix: "short-term".to_owned(),
+                defaulted: vec!["profile_name".to_owned()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        cmd.apply_alias(&mfaws_config).unwrap();
+        cmd.init(&aws_config).unwrap();
+
+        let mid = RoleHop {
+            role_arn: "arn:aws:iam::222222222222:role/mid".to_owned(),
+            role_name: "mid-session".to_owned(),
+            external_id: Some("mid-id".to_owned()),
+        };
+        assert_eq!(cmd.config.profile_name, "base");
+        assert_eq!(cmd.chain, vec![mid.clone()]);
+        assert_eq!(cmd.role_arn(), "arn:aws:iam::333333333333:role/admin");
+        assert_eq!(cmd.role_name(), "mfa-user");
+        assert_eq!(cmd.session.external_id, None);
+
+        let mut cmd = AssumeRole {
+            role_arn: Some("arn:aws:iam::333333333333:role/admin".to_owned()),
+            config: CommonStsConfig {
+                profile_name: "mid".to_owned(),
+                short_term_suffix: "short-term".to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        cmd.init(&aws_config).unwrap();
+
+        assert_eq!(cmd.config.profile_name, "base");
+        assert_eq!(cmd.chain, vec![mid]);
+    }
+
+    #[test]
+    fn scoped_down_profile_name() {
+        let aws_config = AwsConfigHandler::_new("").unwrap();
+        let mut cmd = AssumeRole {
+            role_arn: Some("arn:aws:iam::123456789012:role/admin".to_owned()),
+            policy_path: Some(r#"{"Version": "2012-10-17", "Statement": []}"#.to_owned()),
+            policy_arns: vec!["arn:aws:iam::aws:policy/ReadOnlyAccess".to_owned()],
+            config: CommonStsConfig {
+                profile_name: "dev".to_owned(),
+                short_term_suffix: "short-term".to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        cmd.init(&aws_config).unwrap();
+
+        let hash = cmd.policy_hash().unwrap();
+        assert_eq!(
+            cmd.policy.as_deref(),
+            Some(r#"{"Statement":[],"Version":"2012-10-17"}"#)
+        );
+        assert_eq!(cmd.profile_source().policy_hash.as_deref(), Some(&*hash));
+        assert_eq!(
+            cmd.short_profile_name(),
+            format!("dev_123456789012-role-admin-mfa-user-{}_short-term", hash)
+        );
+
+        cmd.policy_path = Some("{ not json".to_owned());
+        assert!(cmd.init(&aws_config).is_err());
+    }
+
+    #[test]
+    fn tagged_profile_name() {
+        let mut cmd = AssumeRole {
+            role_arn: Some("arn:aws:iam::123456789012:role/admin".to_owned()),
+            role_name: Some("mfa-user".to_owned()),
+            config: CommonStsConfig {
+                profile_name: "dev".to_owned(),
+                short_term_suffix: "short-term".to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let untagged = cmd.short_profile_name();
+        assert_eq!(untagged, "dev_123456789012-role-admin-mfa-user_short-term");
+
+        cmd.session.tags = vec![("Project".to_owned(), "a".to_owned())];
+        let tagged = cmd.short_profile_name();
+        assert_eq!(
+            tagged,
+            format!(
+                "dev_123456789012-role-admin-mfa-user-{}_short-term",
+                cmd.session.hash().unwrap()
+            )
+        );
+        cmd.session.tags = vec![("Project".to_owned(), "b".to_owned())];
+        assert_ne!(cmd.short_profile_name(), tagged);
+        cmd.session.tags.clear();
+        cmd.session.source_identity = Some("jane".to_owned());
+        assert_ne!(cmd.short_profile_name(), untagged);
+    }
+
+    #[test]
+    fn init_without_role_arn() {
+        let aws_config = AwsConfigHandler::_new("").unwrap();
+        let mut cmd = AssumeRole::default();
+        assert!(cmd.init(&aws_config).is_err());
+    }
+}
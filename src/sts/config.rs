@@ -1,4 +1,10 @@
-#[derive(clap::Args, Debug, Default)]
+use crate::{
+    aws_config::{AwsConfigHandler, ConfigProfile},
+    mfaws_config::Alias,
+    output::{OutputMode, Shell},
+};
+
+#[derive(clap::Args, Clone, Debug, Default)]
 pub struct CommonStsConfig {
     #[arg(
         long = "profile",
@@ -11,6 +17,12 @@ pub struct CommonStsConfig {
     pub mfa_device: Option<String>,
     #[arg(long, help = "The one-time password from your MFA device")]
     pub otp: Option<String>,
+    #[arg(
+        long = "otp-command",
+        env = "MFA_OTP_COMMAND",
+        help = "A command that prints the one-time password to stdout, used if --otp is not given"
+    )]
+    pub otp_command: Option<String>,
     #[arg(
         long,
         env = "MFA_DURATION",
@@ -35,14 +47,76 @@ pub struct CommonStsConfig {
         help = "The STS region to use for the AWS client"
     )]
     pub sts_region: String,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        help = "How to output the short-term credentials"
+    )]
+    pub output: OutputMode,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        help = "The shell to format environment variables for (--output env or env-profile)"
+    )]
+    pub shell: Shell,
+    /// Args that were not given and use their default value
+    #[arg(skip)]
+    pub defaulted: Vec<String>,
 }
 
 impl CommonStsConfig {
-    pub fn init(&mut self) -> anyhow::Result<()> {
+    pub fn init(&mut self, aws_config: &AwsConfigHandler) -> anyhow::Result<()> {
+        if let Some(profile) = aws_config.get_profile(&self.profile_name)? {
+            self.merge_config_profile(&profile);
+        }
         self.validate_profile_name()?;
         Ok(())
     }
 
+    /// Fills in values from the AWS config file. Values from flags and
+    /// environment variables take precedence.
+    pub fn merge_config_profile(&mut self, profile: &ConfigProfile) {
+        if self.mfa_device.is_none() {
+            self.mfa_device = profile.mfa_serial.clone();
+        }
+        if self.otp_command.is_none() {
+            self.otp_command = profile.mfa_process.clone();
+        }
+        if self.duration.is_none() {
+            self.duration = profile.duration_seconds;
+        }
+    }
+
+    /// Fills in values from an mfaws alias. Values from flags and environment
+    /// variables take precedence, values from the AWS config file are only
+    /// used if the alias does not set them.
+    pub fn merge_alias(&mut self, alias: &Alias) {
+        if let Some(ref profile) = alias.source_profile {
+            if self.is_defaulted("profile_name") {
+                self.profile_name = profile.clone();
+            }
+        }
+        if let Some(ref suffix) = alias.suffix {
+            if self.is_defaulted("short_term_suffix") {
+                self.short_term_suffix = suffix.clone();
+            }
+        }
+        if let Some(ref region) = alias.region {
+            if self.is_defaulted("sts_region") {
+                self.sts_region = region.clone();
+            }
+        }
+        if self.duration.is_none() {
+            self.duration = alias.duration;
+        }
+    }
+
+    fn is_defaulted(&self, id: &str) -> bool {
+        self.defaulted.iter().any(|d| d == id)
+    }
+
     fn validate_profile_name(&self) -> anyhow::Result<()> {
         if self.profile_name.ends_with(&self.short_term_suffix) {
             anyhow::bail!("Profile name cannot end with the short-term suffix");
@@ -68,4 +142,45 @@ mod test_config {
         config.profile_name = "xshort-term".to_string();
         assert!(config.validate_profile_name().is_err());
     }
+
+    #[test]
+    fn flags_take_precedence_over_config_profile() {
+        let mut config = CommonStsConfig {
+            mfa_device: Some("flag".to_string()),
+            ..Default::default()
+        };
+        config.merge_config_profile(&ConfigProfile {
+            mfa_serial: Some("config".to_string()),
+            duration_seconds: Some(900),
+            ..Default::default()
+        });
+
+        assert_eq!(config.mfa_device.as_deref(), Some("flag"));
+        assert_eq!(config.duration, Some(900));
+    }
+
+    #[test]
+    fn flags_take_precedence_over_alias() {
+        let alias = Alias {
+            source_profile: Some("alias".to_string()),
+            region: Some("eu-central-2".to_string()),
+            suffix: Some("temp".to_string()),
+            duration: Some(900),
+            ..Default::default()
+        };
+        let mut config = CommonStsConfig {
+            profile_name: "flag".to_string(),
+            sts_region: "us-east-1".to_string(),
+            short_term_suffix: "short-term".to_string(),
+            duration: Some(3600),
+            defaulted: vec!["sts_region".to_string(), "short_term_suffix".to_string()],
+            ..Default::default()
+        };
+        config.merge_alias(&alias);
+
+        assert_eq!(config.profile_name, "flag");
+        assert_eq!(config.sts_region, "eu-central-2");
+        assert_eq!(config.short_term_suffix, "temp");
+        assert_eq!(config.duration, Some(3600));
+    }
 }
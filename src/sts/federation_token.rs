@@ -0,0 +1,208 @@
+use crate::{
+    profile::{LongTermProfile, ProfileKind, ProfileSource, ShortTermProfile},
+    sts::{
+        config::CommonStsConfig,
+        extract_sts_err,
+        policy::{policy_descriptors, policy_hash, read_policy, validate_policy_arns},
+        require_lt_profile, ShortTermCredentials,
+    },
+};
+
+#[derive(clap::Args, Debug, Default)]
+#[command(
+    mut_arg("mfa_device", |arg| arg.hide(true)),
+    mut_arg("otp", |arg| arg.hide(true)),
+    mut_arg("otp_command", |arg| arg.hide(true))
+)]
+pub struct FederationToken {
+    #[arg(long = "name", help = "The name of the federated user")]
+    pub name: String,
+    #[arg(
+        long = "policy",
+        value_name = "POLICY",
+        help = "An inline session policy: a file with the policy in JSON, the JSON itself or - to read it from stdin"
+    )]
+    pub policy_path: Option<String>,
+    #[arg(
+        long = "policy-arn",
+        help = "The ARN of a managed policy to use as a session policy. Can be given multiple times"
+    )]
+    pub policy_arns: Vec<String>,
+    #[clap(flatten)]
+    pub config: CommonStsConfig,
+    /// The inline session policy, read from `policy_path` by `init`
+    #[arg(skip)]
+    pub policy: Option<String>,
+}
+
+impl FederationToken {
+    const NAME_LENGTH: std::ops::RangeInclusive<usize> = 2..=32;
+
+    pub fn init(&mut self) -> anyhow::Result<()> {
+        self.validate_name()?;
+        if let Some(ref path) = self.policy_path {
+            self.policy = Some(read_policy(path)?);
+        }
+        validate_policy_arns(&self.policy_arns)?;
+        if self.policy.is_none() && self.policy_arns.is_empty() {
+            warn!("Without a session policy, the federated user has no permissions");
+        }
+        Ok(())
+    }
+
+    pub fn policy_hash(&self) -> Option<String> {
+        policy_hash(self.policy.as_deref(), &self.policy_arns)
+    }
+
+    /// Federated user names are 2 to 32 characters of letters, digits and
+    /// `+=,.@-_`
+    fn validate_name(&self) -> anyhow::Result<()> {
+        let valid_chars = self
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+=,.@-_".contains(c));
+        if !valid_chars || !Self::NAME_LENGTH.contains(&self.name.len()) {
+            anyhow::bail!(
+                "Invalid federated user name \"{}\", use 2 to 32 letters, digits or +=,.@-_",
+                self.name
+            );
+        }
+        Ok(())
+    }
+}
+
+impl ShortTermCredentials for FederationToken {
+    const DEFAULT_DURATION: i32 = 43200;
+    const REQUIRES_MFA: bool = false;
+
+    /// Includes the policy hash, so that a different session policy does not
+    /// reuse the cached profile
+    fn short_profile_name(&self) -> String {
+        let name = match self.policy_hash() {
+            Some(hash) => format!("{}-{}", self.name, hash),
+            None => self.name.clone(),
+        };
+        format!(
+            "{}_federated-{}_{}",
+            self.config.profile_name, name, self.config.short_term_suffix
+        )
+    }
+
+    fn profile_source(&self) -> ProfileSource {
+        ProfileSource {
+            kind: ProfileKind::FederationToken,
+            profile: self.config.profile_name.clone(),
+            duration: self.config.duration,
+            policy_hash: self.policy_hash(),
+            ..Default::default()
+        }
+    }
+
+    fn config(&self) -> &CommonStsConfig {
+        &self.config
+    }
+
+    fn config_mut(&mut self) -> &mut CommonStsConfig {
+        &mut self.config
+    }
+
+    fn log_action(&self) {
+        info!("Getting federation token for \"{}\"", self.name);
+    }
+
+    #[cfg(not(feature = "e2e_test"))]
+    async fn get_credentials(
+        &self,
+        config: &CommonStsConfig,
+        _mfa_token: Option<String>,
+        lt_profile: Option<&LongTermProfile<'_>>,
+    ) -> anyhow::Result<ShortTermProfile<'_>> {
+        let lt_profile = require_lt_profile(lt_profile)?;
+        let output = lt_profile
+            .create_client(config.sts_region.clone())
+            .await
+            .get_federation_token()
+            .name(&self.name)
+            .set_policy(self.policy.clone())
+            .set_policy_arns(policy_descriptors(&self.policy_arns))
+            .duration_seconds(config.duration.unwrap_or(Self::DEFAULT_DURATION))
+            .send()
+            .await
+            .map_err(extract_sts_err)?;
+
+        let mut short_term_profile = ShortTermProfile::try_from(output.credentials)?;
+        if let Some(user) = output.federated_user {
+            short_term_profile.federated_user_arn = Some(user.arn);
+            short_term_profile.federated_user_id = Some(user.federated_user_id);
+        }
+        Ok(short_term_profile)
+    }
+
+    #[cfg(feature = "e2e_test")]
+    async fn get_credentials(
+        &self,
+        config: &CommonStsConfig,
+        _mfa_token: Option<String>,
+        lt_profile: Option<&LongTermProfile<'_>>,
+    ) -> anyhow::Result<ShortTermProfile<'_>> {
+        Ok(ShortTermProfile {
+            access_key: "sts-access-key".to_owned(),
+            secret_key: "sts-secret-key".to_owned(),
+            session_token: "sts-session-token".to_owned(),
+            federated_user_arn: Some(format!("arn:aws:sts::1111:federated-user/{}", self.name)),
+            federated_user_id: Some(format!("1111:{}", self.name)),
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cmd(name: &str) -> FederationToken {
+        FederationToken {
+            name: name.to_owned(),
+            config: CommonStsConfig {
+                profile_name: "dev".to_owned(),
+                short_term_suffix: "short-term".to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn short_profile_name() {
+        assert_eq!(
+            cmd("jane").short_profile_name(),
+            "dev_federated-jane_short-term"
+        );
+    }
+
+    #[test]
+    fn policy_changes_profile_name() {
+        let mut cmd = cmd("jane");
+        cmd.policy = Some(r#"{"Statement":[]}"#.to_owned());
+        let with_policy = cmd.short_profile_name();
+        let hash = cmd.policy_hash().unwrap();
+        assert_eq!(
+            with_policy,
+            format!("dev_federated-jane-{}_short-term", hash)
+        );
+
+        cmd.policy_arns = vec!["arn:aws:iam::aws:policy/ReadOnlyAccess".to_owned()];
+        assert_ne!(cmd.short_profile_name(), with_policy);
+        cmd.policy = None;
+        cmd.policy_arns.clear();
+        assert_eq!(cmd.short_profile_name(), "dev_federated-jane_short-term");
+    }
+
+    #[test]
+    fn validates_name() {
+        assert!(cmd("jane.doe@example").validate_name().is_ok());
+        assert!(cmd("j").validate_name().is_err());
+        assert!(cmd("jane doe").validate_name().is_err());
+        assert!(cmd(&"j".repeat(33)).validate_name().is_err());
+    }
+}
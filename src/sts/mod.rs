@@ -1,29 +1,66 @@
+use std::time::Duration;
+
 use aws_sdk_sts::error::{ProvideErrorMetadata, SdkError};
 
 use crate::{
     creds::CredentialsHandler,
-    profile::{LongTermProfile, ShortTermProfile},
+    otp::run_otp_command,
+    output::{self, OutputMode},
+    profile::{LongTermProfile, ProfileSource, ShortTermProfile},
     sts::config::CommonStsConfig,
+    totp::Totp,
     utils::get_input,
 };
 
 pub mod assume_role;
 pub mod config;
+pub mod federation_token;
+pub mod policy;
+pub mod saml;
+pub mod session_options;
 pub mod session_token;
+pub mod web_identity;
+
+/// A session token that expires sooner than this is not used to sign requests
+pub const MIN_PARENT_VALIDITY: Duration = Duration::from_secs(60);
 
 pub trait ShortTermCredentials {
     const DEFAULT_DURATION: i32;
+    /// Whether the operation needs the MFA device and an MFA code
+    const REQUIRES_MFA: bool = true;
+    /// Whether the request is signed with a long-term profile. If not,
+    /// `get_credentials` is called without one.
+    const REQUIRES_LONG_TERM_PROFILE: bool = true;
 
     async fn get_credentials(
         &self,
         config: &CommonStsConfig,
-        mfa_token: String,
-        lt_profile: &LongTermProfile,
-    ) -> anyhow::Result<ShortTermProfile>;
+        mfa_token: Option<String>,
+        lt_profile: Option<&LongTermProfile>,
+    ) -> anyhow::Result<ShortTermProfile<'_>>;
+
+    /// Gets the credentials with a session token instead of the long-term
+    /// credentials. Only called if `parent_profile_name` is set.
+    async fn get_credentials_with_session(
+        &self,
+        _config: &CommonStsConfig,
+        _parent: &ShortTermProfile<'_>,
+    ) -> anyhow::Result<ShortTermProfile<'_>> {
+        anyhow::bail!("Credentials cannot be requested with a session token")
+    }
+
+    /// A session token profile that can sign the request instead of the
+    /// long-term credentials. It was issued with MFA, so no MFA code is
+    /// needed while it is valid.
+    fn parent_profile_name(&self) -> Option<String> {
+        None
+    }
 
     fn short_profile_name(&self) -> String;
+    fn profile_source(&self) -> ProfileSource;
     fn log_action(&self) -> ();
-    fn config<'c>(&'c self) -> &'c CommonStsConfig;
+    fn config(&self) -> &CommonStsConfig;
+    fn config_mut(&mut self) -> &mut CommonStsConfig;
 }
 
 pub async fn get_st_profile<T>(cmd: T, mut handler: CredentialsHandler) -> anyhow::Result<()>
@@ -31,45 +68,150 @@ where
     T: ShortTermCredentials,
 {
     let config = cmd.config();
-    let lt_profile = handler.get_long_term_profile(cmd.config())?;
+    let st_profile = fetch_st_profile(&cmd, &mut handler, config.output.writes_profile()).await?;
 
-    info!("Using long-term profile \"{}\"", lt_profile.name);
+    match config.output {
+        OutputMode::File => {}
+        OutputMode::CredentialProcess => println!("{}", output::credential_process(&st_profile)),
+        OutputMode::Env => println!("{}", output::env(&st_profile, config.shell)),
+        OutputMode::EnvProfile => println!(
+            "{}",
+            output::env_profile(&cmd.short_profile_name(), config.shell)
+        ),
+    }
 
+    Ok(())
+}
+
+/// Reuses a still valid short-term profile from the credentials file or gets
+/// new credentials from STS. New credentials are only written to the
+/// credentials file if `write` is set.
+pub async fn fetch_st_profile<'a, T>(
+    cmd: &'a T,
+    handler: &mut CredentialsHandler,
+    write: bool,
+) -> anyhow::Result<ShortTermProfile<'a>>
+where
+    T: ShortTermCredentials,
+{
+    let config = cmd.config();
     let st_profile_name = cmd.short_profile_name();
 
-    if let Some(remaining_time) = handler.is_profile_still_valid(&st_profile_name) {
-        match config.force_new_credentials {
-            false => {
-                info!(
-                    "Found existing short-term profile \"{}\" that is valid for the next {}",
-                    st_profile_name, remaining_time
-                );
-                return Ok(());
-            }
-            true => info!(
+    let cached_profile = match handler.is_profile_still_valid(&st_profile_name) {
+        Some(remaining_time) if !config.force_new_credentials => {
+            info!(
+                "Found existing short-term profile \"{}\" that is valid for the next {}",
+                st_profile_name, remaining_time
+            );
+            handler.get_short_term_profile(&st_profile_name)
+        }
+        Some(_) => {
+            info!(
                 "Discarding existing short-term profile \"{}\" (--force was used)",
                 st_profile_name,
-            ),
+            );
+            None
         }
+        None => None,
     };
-    let mfa_token = config
-        .otp
-        .clone()
-        .or_else(|| get_input("Enter MFA code:").ok())
-        .unwrap_or_default();
+    if let Some(st_profile) = cached_profile {
+        return Ok(st_profile);
+    }
 
-    cmd.log_action();
-    let st_profile = cmd.get_credentials(&config, mfa_token, &lt_profile).await?;
+    let (st_profile, source) = request_st_profile(cmd, handler).await?;
 
-    handler.set_short_term_profile(&st_profile, &st_profile_name);
-    handler.to_file()?;
+    if write {
+        handler.set_short_term_profile(&st_profile, &st_profile_name);
+        handler.set_profile_source(&source, &st_profile_name);
+        handler.to_file()?;
 
-    info!(
-        "Successfully added short-term credentials \"{}\"",
-        st_profile_name
-    );
+        info!(
+            "Successfully added short-term credentials \"{}\"",
+            st_profile_name
+        );
+    }
 
-    Ok(())
+    Ok(st_profile)
+}
+
+/// Gets new credentials from STS. If the command accepts a session token and
+/// there is a valid one in the credentials file, it is used instead of the
+/// long-term profile and no MFA code is needed.
+pub async fn request_st_profile<'a, T>(
+    cmd: &'a T,
+    handler: &CredentialsHandler,
+) -> anyhow::Result<(ShortTermProfile<'a>, ProfileSource)>
+where
+    T: ShortTermCredentials,
+{
+    let config = cmd.config();
+    let mut source = cmd.profile_source();
+
+    let parent = cmd.parent_profile_name().and_then(|name| {
+        let profile = handler
+            .get_short_term_profile(&name)
+            .filter(|p| !p.expires_within(MIN_PARENT_VALIDITY))?;
+        Some((name, profile))
+    });
+    if let Some((name, parent)) = parent {
+        info!("Using session token \"{}\"", name);
+        cmd.log_action();
+        let st_profile = cmd.get_credentials_with_session(config, &parent).await?;
+        source.parent = Some(name);
+        return Ok((st_profile, source));
+    }
+
+    if !T::REQUIRES_LONG_TERM_PROFILE {
+        cmd.log_action();
+        let st_profile = cmd.get_credentials(config, None, None).await?;
+        return Ok((st_profile, source));
+    }
+
+    let lt_profile = match T::REQUIRES_MFA {
+        true => handler.get_long_term_profile(config)?,
+        false => handler.get_long_term_credentials(config)?,
+    };
+    info!("Using long-term profile \"{}\"", lt_profile.name);
+
+    let mfa_token = match T::REQUIRES_MFA {
+        true => Some(get_mfa_token(config, &lt_profile).await?),
+        false => None,
+    };
+
+    cmd.log_action();
+    let st_profile = cmd
+        .get_credentials(config, mfa_token, Some(&lt_profile))
+        .await?;
+    Ok((st_profile, source))
+}
+
+/// The long-term profile passed to `get_credentials` by operations that
+/// require one
+pub fn require_lt_profile<'a, 'b>(
+    lt_profile: Option<&'a LongTermProfile<'b>>,
+) -> anyhow::Result<&'a LongTermProfile<'b>> {
+    lt_profile.ok_or_else(|| anyhow::anyhow!("A long-term profile is required"))
+}
+
+/// The MFA code is taken from the `--otp` flag, the output of the OTP command,
+/// generated from the TOTP secret of the long-term profile or read from stdin,
+/// in that order.
+async fn get_mfa_token(
+    config: &CommonStsConfig,
+    lt_profile: &LongTermProfile<'_>,
+) -> anyhow::Result<String> {
+    if let Some(ref otp) = config.otp {
+        return Ok(otp.clone());
+    }
+    if let Some(ref command) = config.otp_command {
+        info!("Getting MFA code from \"{}\"", command);
+        return Ok(run_otp_command(command).await?);
+    }
+    if let Some(ref secret) = lt_profile.totp_secret {
+        info!("Generating MFA code from TOTP secret");
+        return Ok(Totp::from_secret(secret)?.generate_current().await);
+    }
+    Ok(get_input("Enter MFA code:").unwrap_or_default())
 }
 
 pub fn extract_sts_err<T>(err: SdkError<T>) -> anyhow::Error
@@ -81,3 +223,104 @@ where
         err.to_string()
     ))
 }
+
+#[cfg(test)]
+mod test_sts {
+    use super::*;
+    use crate::profile::ProfileKind;
+
+    /// Returns the access key "session" if signed with a session token and
+    /// "long-term" otherwise
+    #[derive(Default)]
+    struct FakeCommand {
+        config: CommonStsConfig,
+    }
+
+    impl ShortTermCredentials for FakeCommand {
+        const DEFAULT_DURATION: i32 = 3600;
+
+        async fn get_credentials_with_session(
+            &self,
+            _config: &CommonStsConfig,
+            _parent: &ShortTermProfile<'_>,
+        ) -> anyhow::Result<ShortTermProfile<'_>> {
+            Ok(ShortTermProfile {
+                access_key: "session".to_owned(),
+                ..Default::default()
+            })
+        }
+
+        async fn get_credentials(
+            &self,
+            _config: &CommonStsConfig,
+            _mfa_token: Option<String>,
+            _lt_profile: Option<&LongTermProfile<'_>>,
+        ) -> anyhow::Result<ShortTermProfile<'_>> {
+            Ok(ShortTermProfile {
+                access_key: "long-term".to_owned(),
+                ..Default::default()
+            })
+        }
+
+        fn parent_profile_name(&self) -> Option<String> {
+            Some("dev-short-term".to_owned())
+        }
+
+        fn short_profile_name(&self) -> String {
+            "dev_role_short-term".to_owned()
+        }
+
+        fn profile_source(&self) -> ProfileSource {
+            ProfileSource {
+                kind: ProfileKind::AssumeRole,
+                profile: "dev".to_owned(),
+                ..Default::default()
+            }
+        }
+
+        fn log_action(&self) {}
+
+        fn config(&self) -> &CommonStsConfig {
+            &self.config
+        }
+
+        fn config_mut(&mut self) -> &mut CommonStsConfig {
+            &mut self.config
+        }
+    }
+
+    fn handler_with_parent(parent_expiration: &str) -> CredentialsHandler {
+        CredentialsHandler::_new(&format!(
+            r#"[dev]
+            aws_access_key_id = 1
+            aws_secret_access_key = 1
+            [dev-short-term]
+            aws_access_key_id = 1
+            aws_secret_access_key = 1
+            aws_session_token = 1
+            expiration = {parent_expiration}"#
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn signs_with_valid_session_token() {
+        let cmd = FakeCommand {
+            config: CommonStsConfig {
+                profile_name: "dev".to_owned(),
+                mfa_device: Some("device".to_owned()),
+                otp: Some("123456".to_owned()),
+                ..Default::default()
+            },
+        };
+        let handler = handler_with_parent("2099-01-01T00:00:00Z");
+        let (profile, source) = request_st_profile(&cmd, &handler).await.unwrap();
+        assert_eq!(profile.access_key, "session");
+        assert_eq!(source.parent.as_deref(), Some("dev-short-term"));
+
+        let handler = handler_with_parent("2000-01-01T00:00:00Z");
+        let (profile, source) = request_st_profile(&cmd, &handler).await.unwrap();
+        assert_eq!(profile.access_key, "long-term");
+        assert!(source.parent.is_none());
+    }
+}
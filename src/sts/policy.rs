@@ -0,0 +1,115 @@
+use std::io::Read;
+
+use aws_sdk_sts::types::PolicyDescriptorType;
+use data_encoding::HEXLOWER;
+use sha1::{Digest, Sha1};
+
+/// AWS accepts at most 10 managed session policies per request
+const MAX_POLICY_ARNS: usize = 10;
+
+/// Reads a session policy from a file, from stdin if the source is `-` or
+/// takes the source itself if it is a JSON object. The policy is validated
+/// and minified, which also keeps it below the packed size limit for longer.
+pub fn read_policy(source: &str) -> anyhow::Result<String> {
+    let policy = match source {
+        "-" => {
+            let mut policy = String::new();
+            std::io::stdin()
+                .read_to_string(&mut policy)
+                .map_err(|e| anyhow::anyhow!("Failed to read policy from stdin: {}", e))?;
+            policy
+        }
+        _ if source.trim_start().starts_with('{') => source.to_owned(),
+        _ => std::fs::read_to_string(source)
+            .map_err(|e| anyhow::anyhow!("Failed to read policy file \"{}\": {}", source, e))?,
+    };
+    parse_policy(&policy)
+}
+
+fn parse_policy(policy: &str) -> anyhow::Result<String> {
+    let value = serde_json::from_str::<serde_json::Value>(policy)
+        .map_err(|e| anyhow::anyhow!("The session policy is not valid JSON: {}", e))?;
+    if !value.is_object() {
+        anyhow::bail!("The session policy must be a JSON object");
+    }
+    Ok(value.to_string())
+}
+
+pub fn validate_policy_arns(policy_arns: &[String]) -> anyhow::Result<()> {
+    if policy_arns.len() > MAX_POLICY_ARNS {
+        anyhow::bail!(
+            "At most {} managed session policies can be given, got {}",
+            MAX_POLICY_ARNS,
+            policy_arns.len()
+        );
+    }
+    if let Some(arn) = policy_arns.iter().find(|arn| !arn.starts_with("arn:")) {
+        anyhow::bail!("Invalid policy ARN \"{}\"", arn);
+    }
+    Ok(())
+}
+
+/// The managed session policies in the form STS expects, or `None` if there
+/// are none
+pub fn policy_descriptors(policy_arns: &[String]) -> Option<Vec<PolicyDescriptorType>> {
+    (!policy_arns.is_empty()).then(|| {
+        policy_arns
+            .iter()
+            .map(|arn| PolicyDescriptorType::builder().arn(arn).build())
+            .collect()
+    })
+}
+
+/// A short fingerprint of the session policies, recorded with the short-term
+/// profile to tell scoped-down sessions apart. `None` if there are no
+/// session policies.
+pub fn policy_hash(policy: Option<&str>, policy_arns: &[String]) -> Option<String> {
+    if policy.is_none() && policy_arns.is_empty() {
+        return None;
+    }
+    let mut arns = policy_arns.to_vec();
+    arns.sort();
+    let mut hasher = Sha1::new();
+    hasher.update(policy.unwrap_or_default());
+    for arn in arns {
+        hasher.update(b"\n");
+        hasher.update(arn);
+    }
+    Some(HEXLOWER.encode(&hasher.finalize()[..4]))
+}
+
+#[cfg(test)]
+mod test_policy {
+    use super::*;
+
+    #[test]
+    fn reads_inline_policy() {
+        let policy = read_policy(r#" { "Version": "2012-10-17", "Statement": [] } "#).unwrap();
+        assert_eq!(policy, r#"{"Statement":[],"Version":"2012-10-17"}"#);
+    }
+
+    #[test]
+    fn err_invalid_policy() {
+        assert!(read_policy("{ not json").is_err());
+        assert!(parse_policy("[]").is_err());
+        assert!(read_policy("/does/not/exist.json").is_err());
+    }
+
+    #[test]
+    fn validates_policy_arns() {
+        let arns = vec!["arn:aws:iam::aws:policy/ReadOnlyAccess".to_owned()];
+        assert!(validate_policy_arns(&arns).is_ok());
+        assert!(validate_policy_arns(&["ReadOnlyAccess".to_owned()]).is_err());
+        assert!(validate_policy_arns(&vec![arns[0].clone(); 11]).is_err());
+    }
+
+    #[test]
+    fn hashes_policies() {
+        let arns = ["arn:b".to_owned(), "arn:a".to_owned()];
+        let reversed = ["arn:a".to_owned(), "arn:b".to_owned()];
+        assert_eq!(policy_hash(None, &[]), None);
+        assert_eq!(policy_hash(None, &arns), policy_hash(None, &reversed));
+        assert_ne!(policy_hash(Some("{}"), &arns), policy_hash(None, &arns));
+        assert_eq!(policy_hash(Some("{}"), &[]).unwrap().len(), 8);
+    }
+}
@@ -0,0 +1,402 @@
+use std::{borrow::Cow, io::Read};
+
+use data_encoding::BASE64;
+
+use crate::{
+    aws_config::AwsConfigHandler,
+    profile::{
+        create_unsigned_client, LongTermProfile, ProfileKind, ProfileSource, ShortTermProfile,
+    },
+    sts::{
+        assume_role::role_profile_name, config::CommonStsConfig, extract_sts_err,
+        ShortTermCredentials,
+    },
+    utils::{get_input, get_tty_input},
+};
+
+#[derive(clap::Args, Debug, Default)]
+#[command(
+    mut_arg("mfa_device", |arg| arg.hide(true)),
+    mut_arg("otp", |arg| arg.hide(true)),
+    mut_arg("otp_command", |arg| arg.hide(true))
+)]
+pub struct Saml {
+    #[arg(
+        long = "assertion",
+        value_name = "FILE",
+        default_value = "-",
+        help = "A file with the base64 encoded SAML assertion, or - to read it from stdin"
+    )]
+    pub assertion_path: String,
+    #[arg(
+        long = "role-arn",
+        env = "AWS_ROLE_ARN",
+        help = "The ARN of the AWS IAM Role you want to assume. If omitted and the assertion grants several roles, you are asked to pick one on the terminal"
+    )]
+    pub role_arn: Option<String>,
+    #[arg(
+        long = "principal-arn",
+        help = "The ARN of the SAML provider in IAM. Read from the assertion if omitted"
+    )]
+    pub principal_arn: Option<String>,
+    #[arg(
+        long = "list-roles",
+        help = "Print the roles the assertion grants and exit"
+    )]
+    pub list_roles: bool,
+    #[clap(flatten)]
+    pub config: CommonStsConfig,
+    /// The base64 encoded SAML assertion, read by `init`
+    #[arg(skip)]
+    pub assertion: String,
+    /// The session name the IdP sets in the assertion
+    #[arg(skip)]
+    pub role_name: Option<String>,
+}
+
+/// A role granted by a SAML assertion, together with the SAML provider that
+/// has to be passed to STS along with it
+#[derive(Debug, Clone, PartialEq)]
+pub struct SamlRole {
+    pub role_arn: String,
+    pub principal_arn: String,
+}
+
+/// The attributes of a SAML assertion that are relevant to AWS
+#[derive(Debug, Default, PartialEq)]
+pub struct SamlAttributes {
+    pub roles: Vec<SamlRole>,
+    pub role_session_name: Option<String>,
+}
+
+impl Saml {
+    const ROLE_ATTRIBUTE: &'static str = "https://aws.amazon.com/SAML/Attributes/Role";
+    const ROLE_SESSION_NAME_ATTRIBUTE: &'static str =
+        "https://aws.amazon.com/SAML/Attributes/RoleSessionName";
+    const DEFAULT_SESSION_NAME: &'static str = "saml";
+
+    /// Reads the assertion and resolves the role to assume. If the role is
+    /// not given, it is picked from the roles the assertion grants.
+    pub fn init(&mut self, aws_config: &AwsConfigHandler) -> anyhow::Result<()> {
+        self.config.init(aws_config)?;
+        self.assertion = read_assertion(&self.assertion_path)?;
+        let attributes = parse_assertion(&self.assertion)?;
+        self.role_name = Some(
+            attributes
+                .role_session_name
+                .clone()
+                .unwrap_or_else(|| Self::DEFAULT_SESSION_NAME.to_owned()),
+        );
+        if self.list_roles {
+            return Ok(());
+        }
+
+        let role = match self.role_arn {
+            Some(ref role_arn) => attributes
+                .roles
+                .iter()
+                .find(|role| &role.role_arn == role_arn)
+                .cloned(),
+            // Stdin is used up by the assertion
+            None if self.assertion_path == "-" => {
+                Some(self.pick_role(&attributes.roles, get_tty_input)?)
+            }
+            None => Some(self.pick_role(&attributes.roles, get_input)?),
+        };
+        if let Some(role) = role {
+            self.role_arn = Some(role.role_arn);
+            self.principal_arn.get_or_insert(role.principal_arn);
+        }
+        if self.principal_arn.is_none() {
+            anyhow::bail!(
+                "Role \"{}\" is not granted by the assertion, use --principal-arn to set the SAML provider",
+                self.role_arn()
+            );
+        }
+        Ok(())
+    }
+
+    fn pick_role(
+        &self,
+        roles: &[SamlRole],
+        input: impl FnOnce(&str) -> anyhow::Result<String>,
+    ) -> anyhow::Result<SamlRole> {
+        match roles {
+            [] => anyhow::bail!("The SAML assertion does not grant any roles"),
+            [role] => Ok(role.clone()),
+            _ => {
+                for (index, role) in roles.iter().enumerate() {
+                    eprintln!("[{}] {}", index + 1, role.role_arn);
+                }
+                let choice =
+                    input(&format!("Choose a role [1-{}]:", roles.len())).map_err(|e| {
+                        anyhow::anyhow!(
+                            "The assertion grants {} roles, use --role-arn to pick one: {}",
+                            roles.len(),
+                            e
+                        )
+                    })?;
+                choice
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|n| n.checked_sub(1))
+                    .and_then(|index| roles.get(index))
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Invalid choice \"{}\"", choice))
+            }
+        }
+    }
+
+    pub fn print_roles(&self) -> anyhow::Result<()> {
+        for role in parse_assertion(&self.assertion)?.roles {
+            println!("{}  {}", role.role_arn, role.principal_arn);
+        }
+        Ok(())
+    }
+
+    pub fn role_arn(&self) -> &str {
+        self.role_arn.as_deref().unwrap_or_default()
+    }
+
+    pub fn role_name(&self) -> &str {
+        self.role_name.as_deref().unwrap_or_default()
+    }
+}
+
+/// Reads the base64 encoded assertion from a file, or from stdin if the path
+/// is `-`. Line breaks are removed.
+fn read_assertion(path: &str) -> anyhow::Result<String> {
+    let assertion = match path {
+        "-" => {
+            let mut assertion = String::new();
+            std::io::stdin()
+                .read_to_string(&mut assertion)
+                .map_err(|e| anyhow::anyhow!("Failed to read SAML assertion from stdin: {}", e))?;
+            assertion
+        }
+        _ => std::fs::read_to_string(path).map_err(|e| {
+            anyhow::anyhow!("Failed to read SAML assertion file \"{}\": {}", path, e)
+        })?,
+    };
+    Ok(assertion.split_whitespace().collect())
+}
+
+/// Decodes the assertion and reads the role and session name attributes.
+/// Each role value is a comma separated pair of a role ARN and a SAML
+/// provider ARN, in either order.
+pub fn parse_assertion(assertion: &str) -> anyhow::Result<SamlAttributes> {
+    let xml = BASE64
+        .decode(assertion.as_bytes())
+        .map_err(|e| anyhow::anyhow!("The SAML assertion is not valid base64: {}", e))?;
+    let xml = String::from_utf8(xml)
+        .map_err(|_| anyhow::anyhow!("The SAML assertion is not valid UTF-8"))?;
+    let doc = roxmltree::Document::parse(&xml)
+        .map_err(|e| anyhow::anyhow!("The SAML assertion is not valid XML: {}", e))?;
+
+    let values = |name: &str| -> Vec<String> {
+        doc.descendants()
+            .filter(|node| {
+                node.tag_name().name() == "Attribute" && node.attribute("Name") == Some(name)
+            })
+            .flat_map(|attribute| attribute.children())
+            .filter(|node| node.tag_name().name() == "AttributeValue")
+            .filter_map(|node| node.text())
+            .map(|text| text.trim().to_owned())
+            .collect()
+    };
+
+    let mut attributes = SamlAttributes {
+        role_session_name: values(Saml::ROLE_SESSION_NAME_ATTRIBUTE).into_iter().next(),
+        ..Default::default()
+    };
+    for value in values(Saml::ROLE_ATTRIBUTE) {
+        let Some((first, second)) = value.split_once(',') else {
+            warn!(
+                "Ignoring malformed role \"{}\" in the SAML assertion",
+                value
+            );
+            continue;
+        };
+        let (first, second) = (first.trim().to_owned(), second.trim().to_owned());
+        let role = match first.contains(":saml-provider/") {
+            true => SamlRole {
+                role_arn: second,
+                principal_arn: first,
+            },
+            false => SamlRole {
+                role_arn: first,
+                principal_arn: second,
+            },
+        };
+        attributes.roles.push(role);
+    }
+    Ok(attributes)
+}
+
+impl ShortTermCredentials for Saml {
+    const DEFAULT_DURATION: i32 = 3600;
+    const REQUIRES_MFA: bool = false;
+    const REQUIRES_LONG_TERM_PROFILE: bool = false;
+
+    fn short_profile_name(&self) -> String {
+        role_profile_name(
+            &self.config.profile_name,
+            self.role_arn(),
+            self.role_name(),
+            &self.config.short_term_suffix,
+        )
+    }
+
+    fn profile_source(&self) -> ProfileSource {
+        ProfileSource {
+            kind: ProfileKind::Saml,
+            profile: self.config.profile_name.clone(),
+            role_session_name: self.role_name.clone(),
+            duration: self.config.duration,
+            ..Default::default()
+        }
+    }
+
+    fn config(&self) -> &CommonStsConfig {
+        &self.config
+    }
+
+    fn config_mut(&mut self) -> &mut CommonStsConfig {
+        &mut self.config
+    }
+
+    fn log_action(&self) {
+        info!(
+            "Assuming role \"{}\" with a SAML assertion from \"{}\"",
+            self.role_arn(),
+            self.principal_arn.as_deref().unwrap_or_default()
+        );
+    }
+
+    #[cfg(not(feature = "e2e_test"))]
+    async fn get_credentials(
+        &self,
+        config: &CommonStsConfig,
+        _mfa_token: Option<String>,
+        _lt_profile: Option<&LongTermProfile<'_>>,
+    ) -> anyhow::Result<ShortTermProfile<'_>> {
+        let output = create_unsigned_client(config.sts_region.clone())
+            .assume_role_with_saml()
+            .role_arn(self.role_arn())
+            .set_principal_arn(self.principal_arn.clone())
+            .saml_assertion(&self.assertion)
+            .duration_seconds(config.duration.unwrap_or(Self::DEFAULT_DURATION))
+            .send()
+            .await
+            .map_err(extract_sts_err)?;
+
+        let mut short_term_profile = ShortTermProfile::try_from(output.credentials)?;
+        short_term_profile.assumed_role_arn = Some(Cow::Borrowed(self.role_arn()));
+        short_term_profile.assumed_role_id = output.assumed_role_user.map(|v| v.assumed_role_id);
+        Ok(short_term_profile)
+    }
+
+    #[cfg(feature = "e2e_test")]
+    async fn get_credentials(
+        &self,
+        config: &CommonStsConfig,
+        _mfa_token: Option<String>,
+        _lt_profile: Option<&LongTermProfile<'_>>,
+    ) -> anyhow::Result<ShortTermProfile<'_>> {
+        Ok(ShortTermProfile {
+            access_key: "sts-access-key".to_owned(),
+            secret_key: "sts-secret-key".to_owned(),
+            session_token: "sts-session-token".to_owned(),
+            assumed_role_id: Some(self.role_arn().to_string()),
+            assumed_role_arn: Some(Cow::Owned("1111/user".to_owned())),
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ASSERTION: &str = r#"<samlp:Response xmlns:samlp="urn:oasis:names:tc:SAML:2.0:protocol">
+      <saml:Assertion xmlns:saml="urn:oasis:names:tc:SAML:2.0:assertion">
+        <saml:AttributeStatement>
+          <saml:Attribute Name="https://aws.amazon.com/SAML/Attributes/RoleSessionName">
+            <saml:AttributeValue>jane@example.com</saml:AttributeValue>
+          </saml:Attribute>
+          <saml:Attribute Name="https://aws.amazon.com/SAML/Attributes/Role">
+            <saml:AttributeValue>arn:aws:iam::111111111111:role/admin,arn:aws:iam::111111111111:saml-provider/idp</saml:AttributeValue>
+            <saml:AttributeValue>arn:aws:iam::222222222222:saml-provider/idp, arn:aws:iam::222222222222:role/readonly</saml:AttributeValue>
+          </saml:Attribute>
+        </saml:AttributeStatement>
+      </saml:Assertion>
+    </samlp:Response>"#;
+
+    fn encoded() -> String {
+        BASE64.encode(ASSERTION.as_bytes())
+    }
+
+    #[test]
+    fn parses_roles() {
+        let attributes = parse_assertion(&encoded()).unwrap();
+        assert_eq!(
+            attributes,
+            SamlAttributes {
+                roles: vec![
+                    SamlRole {
+                        role_arn: "arn:aws:iam::111111111111:role/admin".to_owned(),
+                        principal_arn: "arn:aws:iam::111111111111:saml-provider/idp".to_owned(),
+                    },
+                    SamlRole {
+                        role_arn: "arn:aws:iam::222222222222:role/readonly".to_owned(),
+                        principal_arn: "arn:aws:iam::222222222222:saml-provider/idp".to_owned(),
+                    },
+                ],
+                role_session_name: Some("jane@example.com".to_owned()),
+            }
+        );
+    }
+
+    #[test]
+    fn err_invalid_assertion() {
+        assert!(parse_assertion("not base64!").is_err());
+        assert!(parse_assertion(&BASE64.encode(b"<unclosed>")).is_err());
+    }
+
+    #[test]
+    fn picks_role() {
+        let cmd = Saml::default();
+        let roles = parse_assertion(&encoded()).unwrap().roles;
+        let no_input = |_: &str| anyhow::bail!("No terminal");
+        assert_eq!(cmd.pick_role(&roles[..1], no_input).unwrap(), roles[0]);
+        assert_eq!(
+            cmd.pick_role(&roles, |_| Ok("2".to_owned())).unwrap(),
+            roles[1]
+        );
+        assert!(cmd.pick_role(&roles, |_| Ok("3".to_owned())).is_err());
+        assert!(cmd
+            .pick_role(&roles, no_input)
+            .unwrap_err()
+            .to_string()
+            .contains("use --role-arn"));
+        assert!(cmd.pick_role(&[], no_input).is_err());
+    }
+
+    #[test]
+    fn short_profile_name() {
+        let cmd = Saml {
+            role_arn: Some("arn:aws:iam::111111111111:role/admin".to_owned()),
+            role_name: Some("jane@example.com".to_owned()),
+            config: CommonStsConfig {
+                profile_name: "idp".to_owned(),
+                short_term_suffix: "short-term".to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            cmd.short_profile_name(),
+            "idp_111111111111-role-admin-jane@example.com_short-term"
+        );
+    }
+}
@@ -0,0 +1,309 @@
+use std::{collections::BTreeMap, ops::RangeInclusive};
+
+use aws_sdk_sts::types::Tag;
+use data_encoding::HEXLOWER;
+use sha1::{Digest, Sha1};
+
+use crate::mfaws_config::Alias;
+
+/// Session tags, source identity and external ID of an assumed role
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct SessionOptions {
+    #[arg(
+        long = "tag",
+        value_name = "KEY=VALUE",
+        value_parser = parse_tag,
+        help = "A session tag. Can be given multiple times"
+    )]
+    pub tags: Vec<(String, String)>,
+    #[arg(
+        long = "transitive-tag-key",
+        value_name = "KEY",
+        help = "A session tag that is passed on when assuming further roles with the session. Can be given multiple times"
+    )]
+    pub transitive_tag_keys: Vec<String>,
+    #[arg(
+        long = "source-identity",
+        help = "The source identity of the session, e.g. your username. It is kept when assuming further roles with the session"
+    )]
+    pub source_identity: Option<String>,
+    #[arg(
+        long = "external-id",
+        help = "The external ID the trust policy of the role requires. Read from the profile's \"external_id\" in the AWS config file if omitted"
+    )]
+    pub external_id: Option<String>,
+}
+
+impl SessionOptions {
+    // The limits of the STS API
+    const MAX_TAGS: usize = 50;
+    const TAG_KEY_LENGTH: RangeInclusive<usize> = 1..=128;
+    const TAG_VALUE_LENGTH: RangeInclusive<usize> = 0..=256;
+    const SOURCE_IDENTITY_LENGTH: RangeInclusive<usize> = 2..=64;
+    const EXTERNAL_ID_LENGTH: RangeInclusive<usize> = 2..=1224;
+    const RESERVED_PREFIX: &'static str = "aws:";
+
+    /// Fills in values from an mfaws alias. Tags are merged, a tag given as a
+    /// flag takes precedence over the alias tag with the same key.
+    pub fn merge_alias(&mut self, alias: &Alias) {
+        for (key, value) in &alias.tags {
+            if !self.has_tag(key) {
+                self.tags.push((key.clone(), value.clone()));
+            }
+        }
+        if self.transitive_tag_keys.is_empty() {
+            self.transitive_tag_keys = alias.transitive_tag_keys.clone();
+        }
+        if self.source_identity.is_none() {
+            self.source_identity = alias.source_identity.clone();
+        }
+        if self.external_id.is_none() {
+            self.external_id = alias.external_id.clone();
+        }
+    }
+
+    /// Tag keys are case insensitive
+    fn has_tag(&self, key: &str) -> bool {
+        self.tags.iter().any(|(k, _)| k.eq_ignore_ascii_case(key))
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.validate_tags()?;
+        if let Some(ref identity) = self.source_identity {
+            let valid_chars = identity
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_+=,.@-".contains(c));
+            if !valid_chars
+                || !Self::SOURCE_IDENTITY_LENGTH.contains(&identity.len())
+                || identity.starts_with(Self::RESERVED_PREFIX)
+            {
+                anyhow::bail!(
+                    "Invalid source identity \"{}\", use 2 to 64 letters, digits or _+=,.@-",
+                    identity
+                );
+            }
+        }
+        if let Some(ref external_id) = self.external_id {
+            let valid_chars = external_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_+=,.@:/-".contains(c));
+            if !valid_chars || !Self::EXTERNAL_ID_LENGTH.contains(&external_id.len()) {
+                anyhow::bail!("Invalid external ID, use 2 to 1224 letters, digits or _+=,.@:/-");
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_tags(&self) -> anyhow::Result<()> {
+        if self.tags.len() > Self::MAX_TAGS {
+            anyhow::bail!(
+                "At most {} session tags can be given, got {}",
+                Self::MAX_TAGS,
+                self.tags.len()
+            );
+        }
+        let valid_chars = |s: &str| {
+            s.chars()
+                .all(|c| c.is_alphanumeric() || c.is_whitespace() || "_.:/=+-@".contains(c))
+        };
+        for (index, (key, value)) in self.tags.iter().enumerate() {
+            if !Self::TAG_KEY_LENGTH.contains(&key.chars().count())
+                || !valid_chars(key)
+                || key.to_lowercase().starts_with(Self::RESERVED_PREFIX)
+            {
+                anyhow::bail!(
+                    "Invalid session tag key \"{}\", use 1 to 128 letters, digits, spaces or _.:/=+-@",
+                    key
+                );
+            }
+            if !Self::TAG_VALUE_LENGTH.contains(&value.chars().count()) || !valid_chars(value) {
+                anyhow::bail!(
+                    "Invalid value for session tag \"{}\", use up to 256 letters, digits, spaces or _.:/=+-@",
+                    key
+                );
+            }
+            if self.tags[..index]
+                .iter()
+                .any(|(k, _)| k.eq_ignore_ascii_case(key))
+            {
+                anyhow::bail!("Session tag \"{}\" is given more than once", key);
+            }
+        }
+        if let Some(key) = self
+            .transitive_tag_keys
+            .iter()
+            .find(|key| !self.has_tag(key))
+        {
+            anyhow::bail!("Transitive tag key \"{}\" is not a session tag", key);
+        }
+        Ok(())
+    }
+
+    /// The session tags in the form STS expects, or `None` if there are none
+    pub fn sts_tags(&self) -> anyhow::Result<Option<Vec<Tag>>> {
+        if self.tags.is_empty() {
+            return Ok(None);
+        }
+        let tags = self
+            .tags
+            .iter()
+            .map(|(key, value)| Tag::builder().key(key).value(value).build())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Some(tags))
+    }
+
+    /// The tags as recorded with the short-term profile
+    pub fn tag_map(&self) -> BTreeMap<String, String> {
+        self.tags.iter().cloned().collect()
+    }
+
+    /// A short fingerprint of the options, to tell sessions with different
+    /// tags or identities apart. `None` if no options are set.
+    pub fn hash(&self) -> Option<String> {
+        if self.tags.is_empty()
+            && self.transitive_tag_keys.is_empty()
+            && self.source_identity.is_none()
+            && self.external_id.is_none()
+        {
+            return None;
+        }
+        let mut transitive_tag_keys = self.transitive_tag_keys.clone();
+        transitive_tag_keys.sort();
+        let mut hasher = Sha1::new();
+        for (key, value) in self.tag_map() {
+            hasher.update(format!("tag:{}={}\n", key, value));
+        }
+        for key in transitive_tag_keys {
+            hasher.update(format!("transitive:{}\n", key));
+        }
+        if let Some(ref identity) = self.source_identity {
+            hasher.update(format!("source-identity:{}\n", identity));
+        }
+        if let Some(ref external_id) = self.external_id {
+            hasher.update(format!("external-id:{}\n", external_id));
+        }
+        Some(HEXLOWER.encode(&hasher.finalize()[..4]))
+    }
+}
+
+/// Parses a `KEY=VALUE` session tag. The key ends at the first `=`.
+fn parse_tag(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        .ok_or_else(|| format!("Invalid session tag \"{}\", use KEY=VALUE", s))
+}
+
+#[cfg(test)]
+mod test_session_options {
+    use super::*;
+
+    fn tags(tags: &[(&str, &str)]) -> SessionOptions {
+        SessionOptions {
+            tags: tags
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parses_tags() {
+        assert_eq!(
+            parse_tag("Project=a=b").unwrap(),
+            ("Project".to_owned(), "a=b".to_owned())
+        );
+        assert_eq!(
+            parse_tag("Empty=").unwrap(),
+            ("Empty".to_owned(), "".to_owned())
+        );
+        assert!(parse_tag("Project").is_err());
+    }
+
+    #[test]
+    fn validates_tags() {
+        assert!(tags(&[("Project", "mfaws"), ("CostCenter", "1234")])
+            .validate()
+            .is_ok());
+        assert!(tags(&[("", "value")]).validate().is_err());
+        assert!(tags(&[("aws:Project", "value")]).validate().is_err());
+        assert!(tags(&[("Project", "a;b")]).validate().is_err());
+        assert!(tags(&[("Project", &"v".repeat(257))]).validate().is_err());
+        assert!(tags(&[("Project", "a"), ("project", "b")])
+            .validate()
+            .is_err());
+
+        let many = (0..51)
+            .map(|i| (format!("Key{i}"), String::new()))
+            .collect();
+        let options = SessionOptions {
+            tags: many,
+            ..Default::default()
+        };
+        assert!(options.validate().is_err());
+
+        let mut options = tags(&[("Project", "mfaws")]);
+        options.transitive_tag_keys = vec!["project".to_owned()];
+        assert!(options.validate().is_ok());
+        options.transitive_tag_keys = vec!["CostCenter".to_owned()];
+        assert!(options.validate().is_err());
+    }
+
+    #[test]
+    fn validates_source_identity_and_external_id() {
+        let mut options = SessionOptions {
+            source_identity: Some("jane.doe@example.com".to_owned()),
+            external_id: Some("arn:aws:iam::123456789012:user/x".to_owned()),
+            ..Default::default()
+        };
+        assert!(options.validate().is_ok());
+        options.source_identity = Some("j".to_owned());
+        assert!(options.validate().is_err());
+        options.source_identity = Some("aws:jane".to_owned());
+        assert!(options.validate().is_err());
+        options.source_identity = None;
+        options.external_id = Some("has space".to_owned());
+        assert!(options.validate().is_err());
+    }
+
+    #[test]
+    fn hashes_options() {
+        assert_eq!(SessionOptions::default().hash(), None);
+        let hash = tags(&[("Project", "mfaws"), ("Team", "a")]).hash();
+        assert!(hash.is_some());
+        // The order of the tags does not matter
+        assert_eq!(tags(&[("Team", "a"), ("Project", "mfaws")]).hash(), hash);
+        assert_ne!(tags(&[("Project", "mfaws"), ("Team", "b")]).hash(), hash);
+
+        let mut options = tags(&[("Project", "mfaws"), ("Team", "a")]);
+        options.source_identity = Some("jane".to_owned());
+        assert_ne!(options.hash(), hash);
+        let with_identity = options.hash();
+        options.external_id = Some("id".to_owned());
+        assert_ne!(options.hash(), with_identity);
+    }
+
+    #[test]
+    fn flags_take_precedence_over_alias() {
+        let alias = Alias {
+            tags: BTreeMap::from([
+                ("project".to_owned(), "alias".to_owned()),
+                ("CostCenter".to_owned(), "1234".to_owned()),
+            ]),
+            source_identity: Some("alias".to_owned()),
+            ..Default::default()
+        };
+        let mut options = tags(&[("Project", "flag")]);
+        options.source_identity = Some("flag".to_owned());
+        options.merge_alias(&alias);
+
+        assert_eq!(
+            options.tags,
+            vec![
+                ("Project".to_owned(), "flag".to_owned()),
+                ("CostCenter".to_owned(), "1234".to_owned()),
+            ]
+        );
+        assert_eq!(options.source_identity.as_deref(), Some("flag"));
+    }
+}
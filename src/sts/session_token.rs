@@ -1,6 +1,6 @@
 use crate::{
-    profile::{LongTermProfile, ShortTermProfile},
-    sts::{config::CommonStsConfig, extract_sts_err, ShortTermCredentials},
+    profile::{LongTermProfile, ProfileKind, ProfileSource, ShortTermProfile},
+    sts::{config::CommonStsConfig, extract_sts_err, require_lt_profile, ShortTermCredentials},
 };
 
 #[derive(clap::Args, Debug, Default)]
@@ -9,20 +9,38 @@ pub struct SessionToken {
     pub config: CommonStsConfig,
 }
 
+/// The name of the session token profile for a long-term profile
+pub fn session_profile_name(profile_name: &str, short_term_suffix: &str) -> String {
+    format!("{}-{}", profile_name, short_term_suffix)
+}
+
 impl ShortTermCredentials for SessionToken {
     const DEFAULT_DURATION: i32 = 43200;
 
     fn short_profile_name(&self) -> String {
-        format!(
-            "{}-{}",
-            self.config.profile_name, self.config.short_term_suffix
-        )
+        session_profile_name(&self.config.profile_name, &self.config.short_term_suffix)
     }
 
-    fn config<'c>(&'c self) -> &'c CommonStsConfig {
+    fn profile_source(&self) -> ProfileSource {
+        ProfileSource {
+            kind: ProfileKind::SessionToken,
+            profile: self.config.profile_name.clone(),
+            duration: self.config.duration,
+            mfa_device: self.config.mfa_device.clone(),
+            otp_command: self.config.otp_command.clone(),
+            sts_region: Some(self.config.sts_region.clone()),
+            ..Default::default()
+        }
+    }
+
+    fn config(&self) -> &CommonStsConfig {
         &self.config
     }
 
+    fn config_mut(&mut self) -> &mut CommonStsConfig {
+        &mut self.config
+    }
+
     fn log_action(&self) {
         info!("Getting session token");
     }
@@ -31,16 +49,21 @@ impl ShortTermCredentials for SessionToken {
     async fn get_credentials(
         &self,
         config: &CommonStsConfig,
-        mfa_token: String,
-        lt_profile: &LongTermProfile<'_>,
-    ) -> anyhow::Result<ShortTermProfile> {
+        mfa_token: Option<String>,
+        lt_profile: Option<&LongTermProfile<'_>>,
+    ) -> anyhow::Result<ShortTermProfile<'_>> {
+        let lt_profile = require_lt_profile(lt_profile)?;
         let output = lt_profile
             .create_client(config.sts_region.clone())
             .await
             .get_session_token()
-            .serial_number(lt_profile.mfa_device.to_string())
+            .set_serial_number(
+                mfa_token
+                    .is_some()
+                    .then(|| lt_profile.mfa_device.to_string()),
+            )
             .duration_seconds(config.duration.unwrap_or(Self::DEFAULT_DURATION))
-            .token_code(mfa_token)
+            .set_token_code(mfa_token)
             .send()
             .await
             .map_err(extract_sts_err)?;
@@ -52,9 +75,9 @@ impl ShortTermCredentials for SessionToken {
     async fn get_credentials(
         &self,
         config: &CommonStsConfig,
-        mfa_token: String,
-        lt_profile: &LongTermProfile<'_>,
-    ) -> anyhow::Result<ShortTermProfile> {
+        mfa_token: Option<String>,
+        lt_profile: Option<&LongTermProfile<'_>>,
+    ) -> anyhow::Result<ShortTermProfile<'_>> {
         Ok(ShortTermProfile {
             access_key: "sts-access-key".to_owned(),
             secret_key: "sts-secret-key".to_owned(),
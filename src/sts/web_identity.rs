@@ -0,0 +1,238 @@
+use std::{borrow::Cow, path::PathBuf};
+
+use crate::{
+    aws_config::AwsConfigHandler,
+    profile::{
+        create_unsigned_client, LongTermProfile, ProfileKind, ProfileSource, ShortTermProfile,
+    },
+    sts::{
+        assume_role::role_profile_name, config::CommonStsConfig, extract_sts_err,
+        ShortTermCredentials,
+    },
+};
+
+#[derive(clap::Args, Debug, Default)]
+#[command(
+    mut_arg("mfa_device", |arg| arg.hide(true)),
+    mut_arg("otp", |arg| arg.hide(true)),
+    mut_arg("otp_command", |arg| arg.hide(true))
+)]
+pub struct WebIdentity {
+    #[arg(
+        long = "role-arn",
+        env = "AWS_ROLE_ARN",
+        help = "The ARN of the AWS IAM Role you want to assume. Read from the profile's \"role_arn\" in the AWS config file if omitted"
+    )]
+    pub role_arn: Option<String>,
+    #[arg(
+        long = "role-session-name",
+        env = "AWS_ROLE_SESSION_NAME",
+        help = "Custom friendly session name when assuming the role [default: web-identity]"
+    )]
+    pub role_name: Option<String>,
+    #[arg(
+        long = "token-file",
+        env = "AWS_WEB_IDENTITY_TOKEN_FILE",
+        help = "A file with the OIDC token. Read from the profile's \"web_identity_token_file\" in the AWS config file if omitted"
+    )]
+    pub token_file: Option<PathBuf>,
+    #[arg(
+        long = "token-env",
+        value_name = "VAR",
+        help = "An environment variable with the OIDC token. Takes precedence over --token-file"
+    )]
+    pub token_env: Option<String>,
+    #[clap(flatten)]
+    pub config: CommonStsConfig,
+    /// The OIDC token, read by `init`
+    #[arg(skip)]
+    pub token: String,
+}
+
+impl WebIdentity {
+    const DEFAULT_SESSION_NAME: &'static str = "web-identity";
+
+    /// Merges the settings from the AWS config file and reads the token. The
+    /// profile does not need to exist, it only namespaces the short-term
+    /// profile.
+    pub fn init(&mut self, aws_config: &AwsConfigHandler) -> anyhow::Result<()> {
+        self.config.init(aws_config)?;
+        if let Some(profile) = aws_config.get_profile(&self.config.profile_name)? {
+            if self.role_arn.is_none() {
+                self.role_arn = profile.role_arn;
+            }
+            if self.role_name.is_none() {
+                self.role_name = profile.role_session_name;
+            }
+            if self.token_file.is_none() {
+                self.token_file = profile.web_identity_token_file.map(PathBuf::from);
+            }
+        }
+        self.role_name
+            .get_or_insert_with(|| Self::DEFAULT_SESSION_NAME.to_owned());
+        if self.role_arn.is_none() {
+            anyhow::bail!(
+                "No role ARN for profile \"{}\", use --role-arn or set \"role_arn\" in the AWS config file",
+                self.config.profile_name
+            );
+        }
+        self.token = self.read_token()?;
+        Ok(())
+    }
+
+    fn read_token(&self) -> anyhow::Result<String> {
+        let token = match (&self.token_env, &self.token_file) {
+            (Some(var), _) => std::env::var(var).map_err(|_| {
+                anyhow::anyhow!("Environment variable \"{}\" with the token is not set", var)
+            })?,
+            (None, Some(path)) => std::fs::read_to_string(path).map_err(|e| {
+                anyhow::anyhow!("Failed to read token file \"{}\": {}", path.display(), e)
+            })?,
+            (None, None) => anyhow::bail!("No web identity token, use --token-file or --token-env"),
+        };
+        let token = token.trim();
+        if token.is_empty() {
+            anyhow::bail!("The web identity token is empty");
+        }
+        Ok(token.to_owned())
+    }
+
+    pub fn role_arn(&self) -> &str {
+        self.role_arn.as_deref().unwrap_or_default()
+    }
+
+    pub fn role_name(&self) -> &str {
+        self.role_name.as_deref().unwrap_or_default()
+    }
+}
+
+impl ShortTermCredentials for WebIdentity {
+    const DEFAULT_DURATION: i32 = 3600;
+    const REQUIRES_MFA: bool = false;
+    const REQUIRES_LONG_TERM_PROFILE: bool = false;
+
+    fn short_profile_name(&self) -> String {
+        role_profile_name(
+            &self.config.profile_name,
+            self.role_arn(),
+            self.role_name(),
+            &self.config.short_term_suffix,
+        )
+    }
+
+    fn profile_source(&self) -> ProfileSource {
+        ProfileSource {
+            kind: ProfileKind::WebIdentity,
+            profile: self.config.profile_name.clone(),
+            role_session_name: self.role_name.clone(),
+            duration: self.config.duration,
+            ..Default::default()
+        }
+    }
+
+    fn config(&self) -> &CommonStsConfig {
+        &self.config
+    }
+
+    fn config_mut(&mut self) -> &mut CommonStsConfig {
+        &mut self.config
+    }
+
+    fn log_action(&self) {
+        info!(
+            "Assuming role \"{}\" for \"{}\" with a web identity token",
+            self.role_arn(),
+            self.role_name()
+        );
+    }
+
+    #[cfg(not(feature = "e2e_test"))]
+    async fn get_credentials(
+        &self,
+        config: &CommonStsConfig,
+        _mfa_token: Option<String>,
+        _lt_profile: Option<&LongTermProfile<'_>>,
+    ) -> anyhow::Result<ShortTermProfile<'_>> {
+        let output = create_unsigned_client(config.sts_region.clone())
+            .assume_role_with_web_identity()
+            .role_arn(self.role_arn())
+            .role_session_name(self.role_name())
+            .web_identity_token(&self.token)
+            .duration_seconds(config.duration.unwrap_or(Self::DEFAULT_DURATION))
+            .send()
+            .await
+            .map_err(extract_sts_err)?;
+
+        let mut short_term_profile = ShortTermProfile::try_from(output.credentials)?;
+        short_term_profile.assumed_role_arn = Some(Cow::Borrowed(self.role_arn()));
+        short_term_profile.assumed_role_id = output.assumed_role_user.map(|v| v.assumed_role_id);
+        Ok(short_term_profile)
+    }
+
+    #[cfg(feature = "e2e_test")]
+    async fn get_credentials(
+        &self,
+        config: &CommonStsConfig,
+        _mfa_token: Option<String>,
+        _lt_profile: Option<&LongTermProfile<'_>>,
+    ) -> anyhow::Result<ShortTermProfile<'_>> {
+        Ok(ShortTermProfile {
+            access_key: "sts-access-key".to_owned(),
+            secret_key: "sts-secret-key".to_owned(),
+            session_token: "sts-session-token".to_owned(),
+            assumed_role_id: Some(self.role_arn().to_string()),
+            assumed_role_arn: Some(Cow::Owned("1111/user".to_owned())),
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cmd(token_env: &str) -> WebIdentity {
+        WebIdentity {
+            token_env: Some(token_env.to_owned()),
+            config: CommonStsConfig {
+                profile_name: "ci".to_owned(),
+                short_term_suffix: "short-term".to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn init_from_config_profile() {
+        std::env::set_var("MFAWS_TEST_WEB_IDENTITY_TOKEN", " token\n");
+        let aws_config = AwsConfigHandler::_new(
+            r#"[profile ci]
+            role_arn = arn:aws:iam::123456789012:role/deploy
+            duration_seconds = 900"#,
+        )
+        .unwrap();
+        let mut cmd = cmd("MFAWS_TEST_WEB_IDENTITY_TOKEN");
+        cmd.init(&aws_config).unwrap();
+
+        assert_eq!(cmd.token, "token");
+        assert_eq!(cmd.role_arn(), "arn:aws:iam::123456789012:role/deploy");
+        assert_eq!(cmd.role_name(), "web-identity");
+        assert_eq!(cmd.config.duration, Some(900));
+        assert_eq!(
+            cmd.short_profile_name(),
+            "ci_123456789012-role-deploy-web-identity_short-term"
+        );
+    }
+
+    #[test]
+    fn err_without_token() {
+        let aws_config = AwsConfigHandler::_new("").unwrap();
+        let mut cmd = cmd("MFAWS_TEST_MISSING_TOKEN");
+        cmd.role_arn = Some("arn:aws:iam::123456789012:role/deploy".to_owned());
+        assert!(cmd.init(&aws_config).is_err());
+
+        cmd.token_env = None;
+        assert!(cmd.init(&aws_config).is_err());
+    }
+}
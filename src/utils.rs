@@ -20,26 +20,74 @@ fn format_duration(d: Duration) -> String {
     .to_string()
 }
 
+/// Parses a duration like `90m`, `12h` or `1d 12h`, the counterpart of
+/// `format_duration` that also accepts days
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid duration \"{}\", use e.g. 30m, 12h or 7d", s);
+    let mut secs = 0u64;
+    let mut number = String::new();
+    for c in s.chars().filter(|c| !c.is_whitespace()) {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            'd' => 86400,
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(invalid()),
+        };
+        let n = number.parse::<u64>().map_err(|_| invalid())?;
+        secs = n
+            .checked_mul(unit)
+            .and_then(|n| secs.checked_add(n))
+            .ok_or_else(invalid)?;
+        number.clear();
+    }
+    if !number.is_empty() || secs == 0 {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(secs))
+}
+
 pub fn confirm_prompt(msg: &str) -> bool {
     println!("{} (y/yes/n/no)", msg);
     let mut input = String::new();
-    if let Err(_) = std::io::stdin().read_line(&mut input) {
+    if std::io::stdin().read_line(&mut input).is_err() {
         return false;
     };
-    match input.trim().to_lowercase().as_str() {
-        "y" | "yes" => true,
-        _ => false,
-    }
+    matches!(input.trim().to_lowercase().as_str(), "y" | "yes")
 }
 
 pub fn get_input(msg: &str) -> anyhow::Result<String> {
     let mut input = String::new();
-    println!("{}", msg);
+    // Prompts go to stderr so stdout can be reserved for the output
+    eprintln!("{}", msg);
     std::io::stdin().read_line(&mut input)?;
     input = input.trim().to_owned();
     Ok(input)
 }
 
+/// Like `get_input`, but reads from the terminal instead of stdin, for when
+/// stdin is used for something else
+#[cfg(unix)]
+pub fn get_tty_input(msg: &str) -> anyhow::Result<String> {
+    use std::io::BufRead;
+
+    let tty = std::fs::File::open("/dev/tty")
+        .map_err(|e| anyhow::anyhow!("Failed to open the terminal: {}", e))?;
+    let mut input = String::new();
+    eprintln!("{}", msg);
+    std::io::BufReader::new(tty).read_line(&mut input)?;
+    Ok(input.trim().to_owned())
+}
+
+#[cfg(not(unix))]
+pub fn get_tty_input(_msg: &str) -> anyhow::Result<String> {
+    anyhow::bail!("Reading from the terminal is not supported on this platform")
+}
+
 #[cfg(test)]
 mod test_utils {
     use super::*;
@@ -65,6 +113,19 @@ mod test_utils {
         assert_eq!(format_duration(dur), "".to_string());
     }
     #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("1d 12h"), Ok(Duration::from_secs(129600)));
+        assert_eq!(
+            parse_duration(&format_duration(Duration::from_secs(3599))),
+            Ok(Duration::from_secs(3599))
+        );
+        assert!(parse_duration("12").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("1w").is_err());
+        assert!(parse_duration("0s").is_err());
+    }
+    #[test]
     fn test_format_remaining() {
         let past_time = SystemTime::now() - Duration::from_secs(1);
         assert!(get_remaining_time(past_time).is_none());